@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(entries) = huffman::archive::read(data) {
+        for entry in entries {
+            let _ = entry.decompress();
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use huffman::codebook::Codebook;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(table) = Codebook::parse(s).and_then(|c| c.to_table()) {
+            let (deserialized, _) = huffman::conversion::Table::deserialize(&table.serialize()).unwrap();
+            assert_eq!(deserialized.0, table.0);
+        }
+    }
+});
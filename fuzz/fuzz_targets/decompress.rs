@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let decompressed = huffman::decompress(data);
+    let verified = huffman::verify(data);
+    assert_eq!(decompressed.is_ok(), verified.is_ok());
+    if let (Ok(content), Ok(verified)) = (decompressed, verified) {
+        assert_eq!(content.len() as u64, verified.size);
+        assert!(huffman::inspect::inspect(data).is_ok());
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = huffman::inflate::inflate(data) {
+        assert_eq!(huffman::inflate::inflate(&huffman::deflate::deflate(&content)).unwrap(), content);
+    }
+    let _ = huffman::zlib::decompress(data);
+    let _ = huffman::gzip::decompress(data);
+});
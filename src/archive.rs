@@ -0,0 +1,353 @@
+//! Archives bundling several compressed files.
+//!
+//! An archive is made of magic bytes followed by one entry per file,
+//! each entry being the path and size of the file followed by the
+//! same table, payload size, checksum and payload as a compressed file.
+//!
+//! In a solid archive, a single table built from all the files is stored
+//! after the magic bytes and the entries do not have their own.
+
+use std::collections::{HashMap, HashSet};
+
+use super::container::{self, Header};
+use super::conversion::Table;
+use super::error::{Error, Result};
+use super::tree::{self, Tree};
+
+pub const MAGIC: &[u8; 4] = b"HUFA";
+pub const SUFFIX: &str = ".hfa";
+
+const FLAG_SOLID: u8 = 1;
+
+pub struct Entry<'a> {
+    pub path: String,
+    pub size: u64,
+    header: Header,
+    payload: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    /// Size of the payload, without the entry's header.
+    pub fn compressed_size(&self) -> u64 {
+        self.payload.len() as u64
+    }
+
+    pub fn decompress(&self) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        let size = container::decode_stream(&self.header, self.payload, &mut content)?;
+        if size != self.size {
+            return Err(Error::InvalidPayload(format!(
+                "{} is {} bytes but should be {} bytes", self.path, size, self.size
+            )));
+        }
+        Ok(content)
+    }
+}
+
+/// Check an archived path is relative and stays in the directory it is extracted to.
+pub fn validate_path(path: &str) -> Result<()> {
+    if path.is_empty() || path.starts_with('/') || path.split('/').any(|c| c == "..") {
+        return Err(Error::InvalidHeader(format!("unsafe path {:?}", path)));
+    }
+    Ok(())
+}
+
+/// Table coding all of `files`, to be shared in a solid archive.
+pub fn solid_table(files: &[&[u8]]) -> Table {
+    let mut counter: HashMap<u8, usize> = HashMap::new();
+    for data in files {
+        for (k, v) in tree::count(data) {
+            *counter.entry(k).or_insert(0) += v;
+        }
+    }
+    if counter.is_empty() {
+        return Table::new();
+    }
+    Table::from_tree(&Tree::from_counts(&counter))
+}
+
+pub struct Writer {
+    out: Vec<u8>,
+    /// Table shared by all entries of a solid archive.
+    table: Option<Table>,
+    /// Paths of the entries, which must all be different.
+    paths: HashSet<String>,
+}
+
+impl Default for Writer {
+    fn default() -> Writer {
+        Writer::new()
+    }
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        let mut out = MAGIC.to_vec();
+        out.push(0);
+        Writer { out, table: None, paths: HashSet::new() }
+    }
+
+    /// Solid archive whose entries are all coded with `table`, see `solid_table`.
+    pub fn solid(table: Table) -> Writer {
+        let mut out = MAGIC.to_vec();
+        out.push(FLAG_SOLID);
+        out.extend(table.serialize());
+        Writer { out, table: Some(table), paths: HashSet::new() }
+    }
+
+    /// Add entries at the end of an existing archive.
+    pub fn append(archive: Vec<u8>) -> Result<Writer> {
+        let table = read_solid_table(&archive)?.map(|(table, _)| table);
+        let paths = read(&archive)?.into_iter().map(|entry| entry.path).collect();
+        Ok(Writer { out: archive, table, paths })
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.table.is_some()
+    }
+
+    pub fn add(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        validate_path(path)?;
+        if path.len() > u16::MAX as usize {
+            return Err(Error::InvalidHeader(format!("path {:?} is too long", path)));
+        }
+        if self.paths.contains(path) {
+            return Err(Error::InvalidHeader(format!("{} is already in the archive", path)));
+        }
+        if let Some(table) = &self.table {
+            if let Some(b) = data.iter().find(|b| !table.0.contains_key(b)) {
+                return Err(Error::InvalidTable(format!(
+                    "byte {} of {} is not in the table of the solid archive", b, path
+                )));
+            }
+        }
+        self.paths.insert(path.to_string());
+        self.out.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        self.out.extend_from_slice(path.as_bytes());
+        self.out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+        match &self.table {
+            Some(table) => container::write_payload(table, data, &mut self.out),
+            None => {
+                let table = if data.is_empty() {
+                    Table::new()
+                } else {
+                    Table::from_tree(&Tree::from_data(data))
+                };
+                container::write_stream(&table, data, &mut self.out)
+            },
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+/// Table of a solid archive with the number of bytes before the first entry,
+/// `None` if the archive is not solid.
+fn read_solid_table(data: &[u8]) -> Result<Option<(Table, usize)>> {
+    if !data.starts_with(MAGIC) {
+        return Err(Error::InvalidMagic);
+    }
+    let flags = *data.get(MAGIC.len()).ok_or(Error::UnexpectedEof)?;
+    if flags & !FLAG_SOLID != 0 {
+        return Err(Error::InvalidHeader(format!("unknown flags {:#04x}", flags)));
+    }
+    if flags & FLAG_SOLID == 0 {
+        return Ok(None);
+    }
+    let (table, table_size) = Table::deserialize(&data[MAGIC.len() + 1..])?;
+    table.validate()?;
+    Ok(Some((table, MAGIC.len() + 1 + table_size)))
+}
+
+/// Parse the entries of an archive, without decoding them.
+pub fn read(data: &[u8]) -> Result<Vec<Entry<'_>>> {
+    let (table, start) = match read_solid_table(data)? {
+        Some((table, start)) => (Some(table), start),
+        None                 => (None, MAGIC.len() + 1),
+    };
+    let mut data = &data[start..];
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 2 {
+            return Err(Error::UnexpectedEof);
+        }
+        let path_len = u16::from_be_bytes([data[0], data[1]]) as usize;
+        if data.len() < 2 + path_len + 8 {
+            return Err(Error::UnexpectedEof);
+        }
+        let path = String::from_utf8(data[2..2 + path_len].to_vec())
+            .map_err(|_| Error::InvalidHeader("path is not valid UTF-8".to_string()))?;
+        validate_path(&path)?;
+        let mut size = [0; 8];
+        size.copy_from_slice(&data[2 + path_len..2 + path_len + 8]);
+        let size = u64::from_be_bytes(size);
+        let data_start = &data[2 + path_len + 8..];
+        let (header, payload, rest) = match &table {
+            Some(table) => container::read_payload(table.clone(), data_start)?,
+            None        => container::read_stream(data_start)?,
+        };
+        entries.push(Entry { path, size, header, payload });
+        data = rest;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = Writer::new();
+        writer.add("a.txt", b"abracadabra").unwrap();
+        writer.add("dir/empty", b"").unwrap();
+        writer.add("dir/b.txt", b"hello world").unwrap();
+        let archive = writer.finish();
+
+        let entries = read(&archive).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "dir/empty", "dir/b.txt"]);
+        assert_eq!(entries[0].size, 11);
+        assert_eq!(entries[0].decompress().unwrap(), b"abracadabra");
+        assert_eq!(entries[1].decompress().unwrap(), b"");
+        assert_eq!(entries[2].decompress().unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn solid_round_trip() {
+        let files: [&[u8]; 3] = [b"abracadabra", b"", b"barbara cabal"];
+        let mut writer = Writer::solid(solid_table(&files));
+        for (i, data) in files.iter().enumerate() {
+            writer.add(&format!("file{}", i), data).unwrap();
+        }
+        let archive = writer.finish();
+        let entries = read(&archive).unwrap();
+        assert_eq!(entries.len(), 3);
+        for (entry, data) in entries.iter().zip(files.iter()) {
+            assert_eq!(&entry.decompress().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn solid_smaller() {
+        let files: Vec<Vec<u8>> = (0..20)
+            .map(|i| format!("2020-01-{:02} INFO request served in {}ms", i, i * 7).into_bytes())
+            .collect();
+        let slices: Vec<&[u8]> = files.iter().map(|f| f.as_slice()).collect();
+        let mut solid = Writer::solid(solid_table(&slices));
+        let mut separate = Writer::new();
+        for (i, data) in files.iter().enumerate() {
+            solid.add(&i.to_string(), data).unwrap();
+            separate.add(&i.to_string(), data).unwrap();
+        }
+        assert!(solid.finish().len() < separate.finish().len());
+    }
+
+    #[test]
+    fn solid_append() {
+        let mut writer = Writer::solid(solid_table(&[b"abc"]));
+        writer.add("a", b"abc").unwrap();
+        let mut writer = Writer::append(writer.finish()).unwrap();
+        assert!(writer.is_solid());
+        writer.add("b", b"cab").unwrap();
+        assert!(matches!(writer.add("c", b"xyz"), Err(Error::InvalidTable(_))));
+        let archive = writer.finish();
+        assert_eq!(read(&archive).unwrap()[1].decompress().unwrap(), b"cab");
+    }
+
+    #[test]
+    fn solid_errors() {
+        let mut writer = Writer::solid(solid_table(&[b"abc"]));
+        writer.add("a", b"abc").unwrap();
+        let archive = writer.finish();
+        let table_end = archive.len() - (2 + 1 + 8 + 8 + 4 + 1);
+
+        let mut flags = archive.clone();
+        flags[MAGIC.len()] = 0x03;
+        assert!(matches!(read(&flags), Err(Error::InvalidHeader(_))));
+        // solid flag without the table
+        assert!(matches!(read(&archive[..MAGIC.len() + 1]), Err(Error::UnexpectedEof)));
+        assert!(matches!(read(&archive[..table_end - 1]), Err(Error::UnexpectedEof)));
+        // no entries after the table
+        assert!(read(&archive[..table_end]).unwrap().is_empty());
+        // a table that cannot be decoded
+        let mut oversubscribed = archive[..MAGIC.len() + 1].to_vec();
+        oversubscribed.extend_from_slice(&[0, 0, 0, 9, b'a', 1, 0x00, b'b', 1, 0x80, b'c', 1, 0x80]);
+        assert!(matches!(read(&oversubscribed), Err(Error::InvalidTable(_))));
+        // entry size not matching its payload, which has no table of its own
+        let mut size = archive.clone();
+        size[table_end + 2 + 1 + 7] = 4;
+        assert!(matches!(read(&size).unwrap()[0].decompress(), Err(Error::InvalidPayload(_))));
+        // bytes of a non-solid archive, read against the shared table
+        let mut separate = Writer::new();
+        separate.add("a", b"abc").unwrap();
+        let mut mixed = archive[..table_end].to_vec();
+        mixed.extend_from_slice(&separate.finish()[MAGIC.len() + 1..]);
+        assert!(read(&mixed).and_then(|entries| entries[0].decompress()).is_err());
+    }
+
+    #[test]
+    fn append() {
+        let mut writer = Writer::new();
+        writer.add("a", b"aaa").unwrap();
+        let mut writer = Writer::append(writer.finish()).unwrap();
+        writer.add("b", b"bbb").unwrap();
+        let archive = writer.finish();
+        let entries = read(&archive).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].decompress().unwrap(), b"bbb");
+    }
+
+    #[test]
+    fn duplicate_paths() {
+        let mut writer = Writer::new();
+        writer.add("a", b"aaa").unwrap();
+        assert!(matches!(writer.add("a", b"bbb"), Err(Error::InvalidHeader(_))));
+        writer.add("b", b"bbb").unwrap();
+        let mut writer = Writer::append(writer.finish()).unwrap();
+        assert!(matches!(writer.add("b", b"ccc"), Err(Error::InvalidHeader(_))));
+        let archive = writer.finish();
+        assert_eq!(read(&archive).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(read(&Writer::new().finish()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unsafe_paths() {
+        let mut writer = Writer::new();
+        assert!(writer.add("/etc/passwd", b"").is_err());
+        assert!(writer.add("a/../../b", b"").is_err());
+        assert!(writer.add("", b"").is_err());
+    }
+
+    #[test]
+    fn malformed_input_does_not_panic() {
+        let mut writer = Writer::solid(solid_table(&[b"abracadabra", b"alakazam"]));
+        writer.add("a", b"abracadabra").unwrap();
+        writer.add("dir/b", b"alakazam").unwrap();
+        let valid = writer.finish();
+        for i in 0..valid.len() {
+            for data in [valid[..i].to_vec(), [&valid[..i], &[valid[i] ^ 0x5a], &valid[i + 1..]].concat()] {
+                if let Ok(entries) = read(&data) {
+                    for entry in entries {
+                        let _ = entry.decompress();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let mut writer = Writer::new();
+        writer.add("a", b"abracadabra").unwrap();
+        let archive = writer.finish();
+        assert!(read(&archive[..archive.len() - 1]).is_err());
+        assert!(read(b"HUFF").is_err());
+    }
+}
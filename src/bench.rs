@@ -0,0 +1,190 @@
+//! Throughput of each step of the coding, to compare the performance of changes.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::container::{self, Method, Options};
+use super::conversion::Table;
+use super::error::{Error, Result};
+use super::json;
+use super::range;
+use super::tree::{self, Tree};
+
+/// Size of the synthetic corpora.
+pub const CORPUS_SIZE: usize = 1 << 20;
+
+/// Small deterministic pseudo-random generator, so that corpora are the same on every run.
+/// Tests use it for their random data too.
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+}
+
+/// Built-in corpora: English-like text, uniformly random bytes and a skewed distribution.
+pub fn corpora() -> Vec<(&'static str, Vec<u8>)> {
+    const WORDS: [&str; 16] = [
+        "the", "of", "and", "to", "in", "a", "is", "that",
+        "for", "it", "as", "was", "with", "be", "by", "huffman",
+    ];
+    let mut rng = Lcg(1);
+    let mut text = Vec::with_capacity(CORPUS_SIZE + 16);
+    while text.len() < CORPUS_SIZE {
+        text.extend_from_slice(WORDS[rng.next_u32() as usize % WORDS.len()].as_bytes());
+        text.push(if rng.next_u32().is_multiple_of(12) { b'\n' } else { b' ' });
+    }
+    text.truncate(CORPUS_SIZE);
+
+    vec![("text", text), ("random", random(CORPUS_SIZE)), ("skewed", skewed(CORPUS_SIZE))]
+}
+
+/// `len` uniformly random bytes.
+pub fn random(len: usize) -> Vec<u8> {
+    let mut rng = Lcg(2);
+    (0..len).map(|_| rng.next_u32() as u8).collect()
+}
+
+/// `len` random bytes, each of them half as likely as the previous one.
+pub fn skewed(len: usize) -> Vec<u8> {
+    let mut rng = Lcg(3);
+    (0..len).map(|_| (rng.next_u32() | 1 << 16).trailing_zeros() as u8).collect()
+}
+
+/// Measurements on one input, throughputs being in MB/s of input.
+pub struct Bench {
+    pub name: String,
+    pub size: usize,
+    pub compressed_size: usize,
+    pub ratio: f64,
+    pub iterations: usize,
+    pub histogram: f64,
+    pub tree: f64,
+    pub encode: f64,
+    pub decode: f64,
+    /// Same measurements with the range coder instead of the Huffman coding.
+    pub range_compressed_size: usize,
+    pub range_ratio: f64,
+    pub range_encode: f64,
+    pub range_decode: f64,
+}
+
+/// Fastest of `iterations` runs of `f`, with the result of the last one.
+fn time<T, F: FnMut() -> T>(iterations: usize, mut f: F) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    (best, result.unwrap())
+}
+
+fn throughput(size: usize, duration: Duration) -> f64 {
+    size as f64 / 1e6 / duration.as_secs_f64().max(1e-9)
+}
+
+impl Bench {
+    pub fn run(name: &str, data: &[u8], iterations: usize) -> Result<Bench> {
+        if data.is_empty() {
+            return Err(Error::InvalidPayload("cannot benchmark empty input".to_string()));
+        }
+        let (histogram, counter) = time(iterations, || tree::count(data));
+        let (tree, table) = time(iterations, || Table::from_tree(&Tree::from_counts(&counter)));
+        let (encode, bits) = time(iterations, || table.convert(data));
+        let (decode, decoded) = time(iterations, || table.decode(&bits.data, bits.len));
+        if decoded? != data {
+            return Err(Error::InvalidPayload(format!("{} does not decode to itself", name)));
+        }
+        let compressed_size = container::compress_with_table(data, &table)?.len();
+
+        let (range_encode, range_encoded) = time(iterations, || range::encode(data));
+        let (range_decode, range_decoded) = time(iterations, || range::decode(&range_encoded));
+        if range_decoded? != data {
+            return Err(Error::InvalidPayload(format!("{} does not decode to itself with the range coder", name)));
+        }
+        let options = Options { method: Some(Method::Range), ..Options::default() };
+        let range_compressed_size = container::compress_with(data, &options)?.len();
+        Ok(Bench {
+            name: name.to_string(),
+            size: data.len(),
+            compressed_size,
+            ratio: compressed_size as f64 / data.len() as f64,
+            iterations,
+            histogram: throughput(data.len(), histogram),
+            tree: throughput(data.len(), tree),
+            encode: throughput(data.len(), encode),
+            decode: throughput(data.len(), decode),
+            range_compressed_size,
+            range_ratio: range_compressed_size as f64 / data.len() as f64,
+            range_encode: throughput(data.len(), range_encode),
+            range_decode: throughput(data.len(), range_decode),
+        })
+    }
+
+    pub const CSV_HEADER: &'static str =
+        "name,size,compressed_size,ratio,iterations,histogram_mbps,tree_mbps,encode_mbps,decode_mbps,\
+range_compressed_size,range_ratio,range_encode_mbps,range_decode_mbps";
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":{},\"size\":{},\"compressed_size\":{},\"ratio\":{:.6},\"iterations\":{},\
+\"histogram_mbps\":{:.2},\"tree_mbps\":{:.2},\"encode_mbps\":{:.2},\"decode_mbps\":{:.2},\
+\"range_compressed_size\":{},\"range_ratio\":{:.6},\"range_encode_mbps\":{:.2},\"range_decode_mbps\":{:.2}}}",
+            json::escape(&self.name), self.size, self.compressed_size, self.ratio, self.iterations,
+            self.histogram, self.tree, self.encode, self.decode,
+            self.range_compressed_size, self.range_ratio, self.range_encode, self.range_decode
+        )
+    }
+}
+
+/// One CSV line, without the header.
+impl fmt::Display for Bench {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.name.contains([',', '"']) {
+            format!("\"{}\"", self.name.replace('"', "\"\""))
+        } else {
+            self.name.clone()
+        };
+        write!(
+            f, "{},{},{},{:.6},{},{:.2},{:.2},{:.2},{:.2},{},{:.6},{:.2},{:.2}",
+            name, self.size, self.compressed_size, self.ratio, self.iterations,
+            self.histogram, self.tree, self.encode, self.decode,
+            self.range_compressed_size, self.range_ratio, self.range_encode, self.range_decode
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpora_are_deterministic() {
+        let a = corpora();
+        let b = corpora();
+        assert_eq!(a.len(), 3);
+        for ((name, x), (_, y)) in a.iter().zip(b.iter()) {
+            assert_eq!(x.len(), CORPUS_SIZE, "{}", name);
+            assert!(x == y, "{}", name);
+        }
+    }
+
+    #[test]
+    fn run() {
+        let bench = Bench::run("abra", b"abracadabra", 2).unwrap();
+        assert_eq!(bench.size, 11);
+        assert!(bench.encode > 0.0 && bench.decode > 0.0);
+        assert!(bench.range_encode > 0.0 && bench.range_decode > 0.0 && bench.range_compressed_size > 0);
+        assert_eq!(bench.to_string().split(',').count(), Bench::CSV_HEADER.split(',').count());
+        assert!(json::parse(&bench.to_json()).is_ok());
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Bench::run("empty", b"", 1).is_err());
+    }
+}
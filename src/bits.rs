@@ -1,4 +1,6 @@
-#[derive(Clone)]
+use std::io::{self, Read, Write};
+
+#[derive(Clone, Default)]
 pub struct BitSet {
     pub data: Vec<u8>,
     pub len: usize,
@@ -17,6 +19,21 @@ impl BitSet {
         self.data[0] |= bit << 7;
     }
 
+    pub fn push_bit(&mut self, bit: u8) {
+        if bit != 0 && bit != 1 {
+            panic!("bit should be 1 or 0");
+        }
+        if self.len == self.data.len() * 8 {
+            self.data.push(0);
+        }
+        self.data[self.len / 8] |= bit << (7 - self.len % 8);
+        self.len += 1;
+    }
+
+    pub fn get(&self, i: usize) -> u8 {
+        (self.data[i / 8] >> (7 - i % 8)) & 1
+    }
+
     fn shift_right_once(&mut self) {
         if self.len == self.data.len() * 8 {
             self.data.push(0);
@@ -30,7 +47,7 @@ impl BitSet {
     }
 
     fn shift_left_once(&mut self) {
-        if self.data.len() == 0 || self.len == 0 {
+        if self.data.is_empty() || self.len == 0 {
             return;
         }
         for i in 0..(self.data.len() - 1) {
@@ -44,7 +61,7 @@ impl BitSet {
     }
 
     pub fn concat(&mut self, other: &BitSet) {
-        if self.len % 8 == 0 {
+        if self.len.is_multiple_of(8) {
             self.data.extend(other.data.iter());
             self.len += other.len;
             return;
@@ -70,7 +87,7 @@ impl BitSet {
                 return false;
             }
         }
-        let mask = if other.len % 8 == 0 {
+        let mask = if other.len.is_multiple_of(8) {
             0xff
         } else {
             (0xff >> (8 - other.len % 8)) << (8 - other.len % 8)
@@ -81,6 +98,267 @@ impl BitSet {
     }
 }
 
+/// Writes bits, most significant first, to an underlying writer.
+pub struct BitWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    acc: u64,
+    acc_len: u32,
+    len: u64,
+}
+
+const BUFFER_SIZE: usize = 1 << 16;
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(inner: W) -> BitWriter<W> {
+        BitWriter { inner, buffer: Vec::with_capacity(BUFFER_SIZE), acc: 0, acc_len: 0, len: 0 }
+    }
+
+    /// Number of bits written so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Write the `len` lowest bits of `value`, `len` being at most 56.
+    pub fn write_bits(&mut self, value: u64, len: u32) -> io::Result<()> {
+        debug_assert!(len <= 56);
+        if len == 0 {
+            return Ok(());
+        }
+        self.acc = (self.acc << len) | (value & (u64::MAX >> (64 - len)));
+        self.acc_len += len;
+        self.len += len as u64;
+        while self.acc_len >= 8 {
+            self.acc_len -= 8;
+            self.buffer.push((self.acc >> self.acc_len) as u8);
+        }
+        self.acc &= (1 << self.acc_len) - 1;
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    pub fn write_bitset(&mut self, bits: &BitSet) -> io::Result<()> {
+        for i in 0..bits.len / 8 {
+            self.write_bits(bits.data[i] as u64, 8)?;
+        }
+        if !bits.len.is_multiple_of(8) {
+            let rest = (bits.len % 8) as u32;
+            self.write_bits((bits.data[bits.len / 8] >> (8 - rest)) as u64, rest)?;
+        }
+        Ok(())
+    }
+
+    /// Write whole bytes, directly when the writer is on a byte boundary.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.acc_len != 0 {
+            for byte in bytes {
+                self.write_bits(*byte as u64, 8)?;
+            }
+            return Ok(());
+        }
+        self.inner.write_all(&self.buffer)?;
+        self.buffer.clear();
+        self.inner.write_all(bytes)?;
+        self.len += bytes.len() as u64 * 8;
+        Ok(())
+    }
+
+    /// Pad the last byte with zeroes and flush, returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.acc_len > 0 {
+            self.buffer.push((self.acc << (8 - self.acc_len)) as u8);
+        }
+        self.inner.write_all(&self.buffer)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads bits, most significant first, from an underlying reader.
+pub struct BitReader<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    bit: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R) -> BitReader<R> {
+        BitReader { inner, buffer: vec![0; BUFFER_SIZE], pos: 0, filled: 0, bit: 0 }
+    }
+
+    /// Next bit, `None` at the end of the underlying reader.
+    pub fn read_bit(&mut self) -> io::Result<Option<u8>> {
+        if self.pos == self.filled {
+            self.filled = loop {
+                match self.inner.read(&mut self.buffer) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            };
+            self.pos = 0;
+            if self.filled == 0 {
+                return Ok(None);
+            }
+        }
+        let bit = (self.buffer[self.pos] >> (7 - self.bit)) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(Some(bit))
+    }
+
+    /// Bits left in the byte being read, they are the padding at the end of a stream.
+    pub fn remaining_in_byte(&self) -> u8 {
+        if self.bit == 0 {
+            return 0;
+        }
+        self.buffer[self.pos] & (0xff >> self.bit)
+    }
+
+    /// Whether the underlying reader has bytes left after the current one.
+    pub fn has_more_bytes(&mut self) -> io::Result<bool> {
+        let next = self.pos + if self.bit == 0 { 0 } else { 1 };
+        if next < self.filled {
+            return Ok(true);
+        }
+        let mut byte = [0];
+        loop {
+            match self.inner.read(&mut byte) {
+                Ok(n) => return Ok(n > 0),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Writes bits to a vector, least significant first, as DEFLATE packs them.
+#[derive(Default)]
+pub struct LsbWriter {
+    out: Vec<u8>,
+    acc: u64,
+    acc_len: u32,
+}
+
+impl LsbWriter {
+    pub fn new() -> LsbWriter {
+        LsbWriter::default()
+    }
+
+    /// Number of bits written so far.
+    pub fn len(&self) -> u64 {
+        self.out.len() as u64 * 8 + self.acc_len as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the `len` lowest bits of `value`, `len` being at most 32.
+    pub fn write_bits(&mut self, value: u32, len: u32) {
+        debug_assert!(len <= 32);
+        self.acc |= (value as u64 & ((1 << len) - 1)) << self.acc_len;
+        self.acc_len += len;
+        while self.acc_len >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.acc_len -= 8;
+        }
+    }
+
+    /// Write a Huffman code of `len` bits, its most significant bit first.
+    pub fn write_code(&mut self, code: u32, len: u32) {
+        if len > 0 {
+            self.write_bits(code.reverse_bits() >> (32 - len), len);
+        }
+    }
+
+    /// Pad the last byte with zeroes.
+    pub fn align(&mut self) {
+        if self.acc_len > 0 {
+            self.write_bits(0, 8 - self.acc_len);
+        }
+    }
+
+    /// Write whole bytes, after padding the last byte.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.align();
+        self.out.extend_from_slice(bytes);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.out
+    }
+}
+
+/// Reads bits from a slice, least significant first, as DEFLATE packs them.
+pub struct LsbReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl<'a> LsbReader<'a> {
+    pub fn new(data: &'a [u8]) -> LsbReader<'a> {
+        LsbReader { data, pos: 0, bit: 0 }
+    }
+
+    /// Next bit, `None` at the end of the data.
+    pub fn read_bit(&mut self) -> Option<u8> {
+        let bit = (self.data.get(self.pos)? >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Some(bit)
+    }
+
+    /// Next `len` bits as an integer whose least significant bit was read first,
+    /// `len` being at most 32.
+    pub fn read_bits(&mut self, len: u32) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..len {
+            value |= (self.read_bit()? as u32) << i;
+        }
+        Some(value)
+    }
+
+    /// Skip the rest of the byte being read.
+    pub fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+
+    /// Next `len` bytes, after skipping the rest of the byte being read.
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        self.align();
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// Number of bytes read, counting the one being read.
+    pub fn position(&self) -> usize {
+        self.pos + if self.bit == 0 { 0 } else { 1 }
+    }
+}
+
 use std::ops;
 
 impl ops::ShrAssign<usize> for BitSet {
@@ -125,6 +403,15 @@ impl fmt::Debug for BitSet {
     }
 }
 
+impl fmt::Display for BitSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in 0..self.len {
+            write!(f, "{}", self.get(i))?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,6 +651,88 @@ mod tests {
         assert_eq!(a, bitset_from_str("00101010"));
     }
 
+    #[test]
+    fn push_bit() {
+        let mut a = bitset_from_str("1011011");
+        a.push_bit(1);
+        a.push_bit(0);
+        assert_eq!(a, bitset_from_str("101101110"));
+        assert_eq!(a.to_string(), "101101110");
+    }
+
+    #[test]
+    fn bit_writer() {
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bitset(&bitset_from_str("1111000011")).unwrap();
+        writer.write_bits(0, 0).unwrap();
+        writer.write_bits(0b1, 1).unwrap();
+        assert_eq!(writer.len(), 14);
+        assert_eq!(writer.finish().unwrap(), vec![0b10111110, 0b00011100]);
+    }
+
+    #[test]
+    fn bit_writer_bytes() {
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_bytes(b"ab").unwrap();
+        writer.write_bits(1, 1).unwrap();
+        writer.write_bytes(&[0xff]).unwrap();
+        assert_eq!(writer.len(), 25);
+        assert_eq!(writer.finish().unwrap(), vec![b'a', b'b', 0xff, 0x80]);
+    }
+
+    #[test]
+    fn bit_reader() {
+        let data = [0b10111110u8, 0b00011100];
+        let mut reader = BitReader::new(&data[..]);
+        let mut bits = String::new();
+        for _ in 0..14 {
+            bits.push_str(&reader.read_bit().unwrap().unwrap().to_string());
+        }
+        assert_eq!(bits, "10111110000111");
+        assert_eq!(reader.remaining_in_byte(), 0);
+        assert!(!reader.has_more_bytes().unwrap());
+        reader.read_bit().unwrap();
+        reader.read_bit().unwrap();
+        assert_eq!(reader.read_bit().unwrap(), None);
+    }
+
+    #[test]
+    fn bit_reader_padding() {
+        let data = [0b10100001u8, 0xff];
+        let mut reader = BitReader::new(&data[..]);
+        reader.read_bit().unwrap();
+        reader.read_bit().unwrap();
+        assert_eq!(reader.remaining_in_byte(), 0b100001);
+        assert!(reader.has_more_bytes().unwrap());
+    }
+
+    #[test]
+    fn lsb_writer() {
+        let mut writer = LsbWriter::new();
+        writer.write_bits(0b101, 3);
+        writer.write_code(0b1100, 4);
+        writer.write_bits(0x1ff, 9);
+        assert_eq!(writer.len(), 16);
+        writer.write_bits(1, 1);
+        writer.write_bytes(b"a");
+        assert_eq!(writer.finish(), vec![0b10011101, 0xff, 0x01, b'a']);
+    }
+
+    #[test]
+    fn lsb_reader() {
+        let data = [0b10011101u8, 0xff, 0x01, b'a'];
+        let mut reader = LsbReader::new(&data);
+        assert_eq!(reader.read_bits(3), Some(0b101));
+        assert_eq!(reader.read_bits(4), Some(0b0011));
+        assert_eq!(reader.read_bits(9), Some(0x1ff));
+        assert_eq!(reader.read_bit(), Some(1));
+        assert_eq!(reader.position(), 3);
+        assert_eq!(reader.read_bytes(1), Some(&b"a"[..]));
+        assert_eq!(reader.read_bit(), None);
+        assert_eq!(reader.read_bytes(usize::MAX), None);
+    }
+
     #[test]
     fn concat_one_chunk() {
         let mut a = bitset_from_str("101");
@@ -0,0 +1,296 @@
+//! Burrows–Wheeler transform and move-to-front followed by Huffman coding, as in bzip2.
+//!
+//! The transform groups bytes that appear in similar contexts, which move-to-front
+//! turns into many small values, mostly zeroes, that the Huffman coding codes in few bits.
+//! Blocks of the data are transformed separately and then coded with a single table:
+//!
+//! ```text
+//! 8 byte unsigned int: size of the data
+//! 4 byte unsigned int: size of the blocks
+//! 4 byte unsigned int per block: primary index of the block
+//! table (as in a compressed file)
+//! 8 byte unsigned int: size of the codes in bits
+//! codes of the move-to-front output of every block, padded with zeroes to a 8-bit boundary
+//! ```
+
+use super::bits::{BitReader, BitWriter};
+use super::conversion::Table;
+use super::error::{Error, Result};
+use super::tree::Tree;
+
+/// Size of the blocks the data is transformed by, as bzip2's largest.
+pub const BLOCK_SIZE: usize = 900_000;
+
+/// Largest block size accepted when decoding, the transform taking memory proportional to it.
+const MAX_BLOCK_SIZE: usize = 1 << 24;
+
+/// Start of the suffixes of `data` in lexicographic order, a suffix being smaller than those it is a prefix of.
+///
+/// Built by prefix doubling: suffixes sorted by their first `k` bytes are sorted by their first
+/// `2k` bytes using the rank of the suffix starting `k` bytes later, until all ranks are distinct.
+/// Each step is a counting sort, as the order of the second halves is the current order shifted by `k`.
+pub fn suffix_array(data: &[u8]) -> Vec<usize> {
+    let n = data.len();
+    let mut sa: Vec<usize> = (0..n).collect();
+    sa.sort_by_key(|i| data[*i]);
+    if n < 2 {
+        return sa;
+    }
+    // rank 0 is for the empty suffix past the end
+    let mut rank: Vec<usize> = data.iter().map(|b| *b as usize + 1).collect();
+    let mut new_rank = vec![0; n];
+    let mut by_second = Vec::with_capacity(n);
+    let mut starts = vec![0; n.max(256) + 2];
+    let mut k = 1;
+    loop {
+        // suffixes whose second half is empty come first
+        by_second.clear();
+        by_second.extend(n - k..n);
+        by_second.extend(sa.iter().filter(|i| **i >= k).map(|i| i - k));
+
+        starts.iter_mut().for_each(|s| *s = 0);
+        for i in &by_second {
+            starts[rank[*i] + 1] += 1;
+        }
+        for r in 1..starts.len() {
+            starts[r] += starts[r - 1];
+        }
+        for i in &by_second {
+            sa[starts[rank[*i]]] = *i;
+            starts[rank[*i]] += 1;
+        }
+
+        let key = |i: usize| (rank[i], if i + k < n { rank[i + k] } else { 0 });
+        new_rank[sa[0]] = 1;
+        for w in 1..n {
+            new_rank[sa[w]] = new_rank[sa[w - 1]] + if key(sa[w]) != key(sa[w - 1]) { 1 } else { 0 };
+        }
+        std::mem::swap(&mut rank, &mut new_rank);
+        if rank[sa[n - 1]] == n {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+/// Last column of the sorted rotations of `data` followed by an end marker, without the marker,
+/// with the index of the marker in it.
+pub fn transform(data: &[u8]) -> (Vec<u8>, usize) {
+    let n = data.len();
+    let mut last = Vec::with_capacity(n);
+    // the rotation starting with the marker is the first one, it ends with the last byte
+    let mut primary = 0;
+    if n > 0 {
+        last.push(data[n - 1]);
+    }
+    for (row, start) in suffix_array(data).into_iter().enumerate() {
+        if start == 0 {
+            primary = row + 1;
+        } else {
+            last.push(data[start - 1]);
+        }
+    }
+    (last, primary)
+}
+
+/// Data whose transform is `last` and `primary`.
+pub fn inverse_transform(last: &[u8], primary: usize) -> Result<Vec<u8>> {
+    let n = last.len();
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    if primary == 0 || primary > n {
+        return Err(Error::InvalidPayload(format!("invalid primary index {}", primary)));
+    }
+    // byte of each row of the last column, 0 being the marker
+    let column = |row: usize| match row {
+        r if r < primary => last[r] as usize + 1,
+        r if r == primary => 0,
+        r => last[r - 1] as usize + 1,
+    };
+    // first row starting with each symbol
+    let mut starts = [0usize; 258];
+    for row in 0..=n {
+        starts[column(row) + 1] += 1;
+    }
+    for i in 1..starts.len() {
+        starts[i] += starts[i - 1];
+    }
+    // row of the rotation starting with the byte at the end of each row
+    let mut next = vec![0u32; n + 1];
+    for (row, next) in next.iter_mut().enumerate() {
+        let symbol = column(row);
+        *next = starts[symbol] as u32;
+        starts[symbol] += 1;
+    }
+
+    let mut out = vec![0; n];
+    let mut row = 0;
+    for i in (0..n).rev() {
+        let symbol = column(row);
+        if symbol == 0 {
+            return Err(Error::InvalidPayload("invalid transform".to_string()));
+        }
+        out[i] = (symbol - 1) as u8;
+        row = next[row] as usize;
+    }
+    Ok(out)
+}
+
+/// Replace each byte with its index in a list of bytes, which it is then moved to the front of.
+pub fn move_to_front(data: &[u8]) -> Vec<u8> {
+    let mut list: Vec<u8> = (0..=255).collect();
+    data.iter()
+        .map(|b| {
+            let i = list.iter().position(|x| x == b).unwrap();
+            list[..=i].rotate_right(1);
+            i as u8
+        })
+        .collect()
+}
+
+pub fn inverse_move_to_front(data: &[u8]) -> Vec<u8> {
+    let mut list: Vec<u8> = (0..=255).collect();
+    data.iter()
+        .map(|i| {
+            let i = *i as usize;
+            let b = list[i];
+            list[..=i].rotate_right(1);
+            b
+        })
+        .collect()
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    encode_blocks(data, BLOCK_SIZE)
+}
+
+fn encode_blocks(data: &[u8], block_size: usize) -> Vec<u8> {
+    let mut out = (data.len() as u64).to_be_bytes().to_vec();
+    out.extend_from_slice(&(block_size as u32).to_be_bytes());
+    let mut symbols = Vec::with_capacity(data.len());
+    for block in data.chunks(block_size) {
+        let (last, primary) = transform(block);
+        out.extend_from_slice(&(primary as u32).to_be_bytes());
+        symbols.extend(move_to_front(&last));
+    }
+
+    let table = if symbols.is_empty() { Table::new() } else { Table::from_tree(&Tree::from_data(&symbols)) };
+    out.extend(table.serialize());
+    let mut writer = BitWriter::new(Vec::new());
+    // the table has a code for every symbol and writing to a vector cannot fail
+    table.encode_to(&symbols, &mut writer).unwrap();
+    out.extend_from_slice(&writer.len().to_be_bytes());
+    out.extend(writer.finish().unwrap());
+    out
+}
+
+fn read_u64(data: &[u8]) -> Result<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(data.get(..8).ok_or(Error::UnexpectedEof)?);
+    Ok(u64::from_be_bytes(bytes))
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let size = read_u64(data)?;
+    let block_size = data.get(8..12).ok_or(Error::UnexpectedEof)?;
+    let block_size = u32::from_be_bytes([block_size[0], block_size[1], block_size[2], block_size[3]]) as usize;
+    if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+        return Err(Error::InvalidPayload(format!("invalid block size {}", block_size)));
+    }
+    let mut data = &data[12..];
+    let mut primaries = Vec::new();
+    for _ in 0..size.div_ceil(block_size as u64) {
+        let primary = data.get(..4).ok_or(Error::UnexpectedEof)?;
+        primaries.push(u32::from_be_bytes([primary[0], primary[1], primary[2], primary[3]]) as usize);
+        data = &data[4..];
+    }
+    let (table, table_size) = Table::deserialize(data)?;
+    let bits_len = read_u64(&data[table_size..])?;
+    let mut reader = BitReader::new(&data[table_size + 8..]);
+    let mut symbols = Vec::new();
+    table.decode_from(&mut reader, bits_len, &mut symbols)?;
+    if reader.remaining_in_byte() != 0 {
+        return Err(Error::InvalidPayload("padding bits are not zero".to_string()));
+    }
+    if reader.has_more_bytes()? {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    if symbols.len() as u64 != size {
+        return Err(Error::InvalidPayload(format!("decoded {} bytes instead of {}", symbols.len(), size)));
+    }
+
+    let mut out = Vec::with_capacity(symbols.len());
+    for (block, primary) in symbols.chunks(block_size).zip(primaries) {
+        out.extend(inverse_transform(&inverse_move_to_front(block), primary)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(suffix_array(b"banana"), vec![5, 3, 1, 0, 4, 2]);
+        assert_eq!(suffix_array(b"aaaa"), vec![3, 2, 1, 0]);
+        assert!(suffix_array(b"").is_empty());
+
+        let data: Vec<u8> = (0..500u32).map(|i| (i * i % 7 + i % 3) as u8).collect();
+        let mut expected: Vec<usize> = (0..data.len()).collect();
+        expected.sort_by_key(|i| &data[*i..]);
+        assert_eq!(suffix_array(&data), expected);
+    }
+
+    #[test]
+    fn banana() {
+        // rotations of "banana$" sorted, with the marker smallest: $banana a$banan ana$ban
+        // anana$b banana$ na$bana nana$ba, the last column being annb$aa
+        assert_eq!(transform(b"banana"), (b"annbaa".to_vec(), 4));
+        assert_eq!(inverse_transform(b"annbaa", 4).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn transform_round_trip() {
+        for data in [&b""[..], b"a", b"abracadabra", b"aaaaaaaa", b"mississippi", &[0, 255, 0, 255, 1]] {
+            let (last, primary) = transform(data);
+            assert_eq!(inverse_transform(&last, primary).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn mtf() {
+        assert_eq!(move_to_front(b"aaabbba"), vec![97, 0, 0, 98, 0, 0, 1]);
+        assert_eq!(inverse_move_to_front(&move_to_front(b"abracadabra")), b"abracadabra");
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = b"it was the best of times, it was the worst of times, it was the age of wisdom, \
+it was the age of foolishness, it was the epoch of belief, it was the epoch of incredulity";
+        let encoded = encode(text);
+        assert_eq!(decode(&encoded).unwrap(), &text[..]);
+        assert_eq!(decode(&encode(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn several_blocks() {
+        let data: Vec<u8> = (0..2500).map(|i| (i % 7 * (i % 13)) as u8).collect();
+        let encoded = encode_blocks(&data, 1000);
+        assert_eq!(decode(&encoded).unwrap(), data);
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn errors() {
+        let encoded = encode(b"abracadabra");
+        assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(inverse_transform(b"abc", 0).is_err());
+        assert!(inverse_transform(b"abc", 4).is_err());
+        let mut invalid_primary = encoded.clone();
+        invalid_primary[15] = 200;
+        assert!(decode(&invalid_primary).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Canonical Huffman codes, given by the code length of each symbol alone.
+//!
+//! Codes of the same length are consecutive integers in the order of their symbols,
+//! and each length starts after the codes of the shorter ones, as in DEFLATE (RFC 1951 3.2.2).
+
+use std::collections::HashMap;
+
+use super::conversion;
+use super::error::{Error, Result};
+use super::tree::Tree;
+
+/// Code length of each symbol given its count, 0 for symbols that do not appear,
+/// no code being longer than `max_len`.
+///
+/// The lengths are those of the Huffman tree unless it is too deep,
+/// in which case they are computed by the package-merge algorithm.
+pub fn lengths(counts: &[usize], max_len: usize) -> Vec<u8> {
+    let mut lengths = vec![0; counts.len()];
+    let counter: HashMap<usize, usize> = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c > 0)
+        .map(|(s, c)| (s, *c))
+        .collect();
+    match counter.len() {
+        0 => return lengths,
+        // a tree made of a single leaf would give it an empty code
+        1 => {
+            lengths[*counter.keys().next().unwrap()] = 1;
+            return lengths;
+        },
+        _ => (),
+    }
+    for (symbol, code) in Tree::from_counts(&counter).to_hash_map() {
+        lengths[symbol] = code.len as u8;
+    }
+    if lengths.iter().any(|len| *len as usize > max_len) {
+        lengths = package_merge(counts, max_len);
+    }
+    lengths
+}
+
+/// Optimal code lengths no longer than `max_len` (Larmore and Hirschberg).
+///
+/// Each symbol is a coin of its count at every length, and coins are paired into packages
+/// from the longest length up: the `2n - 2` cheapest items of the last length give
+/// the length of each symbol as the number of them it is part of.
+fn package_merge(counts: &[usize], max_len: usize) -> Vec<u8> {
+    let mut leaves: Vec<(usize, usize)> = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c > 0)
+        .map(|(s, c)| (*c, s))
+        .collect();
+    leaves.sort_unstable();
+    assert!(leaves.len() <= 1 << max_len, "{} symbols do not fit in {} bits", leaves.len(), max_len);
+
+    // weight of each item with the symbols it is made of
+    let mut items: Vec<(usize, Vec<usize>)> = Vec::new();
+    for _ in 0..max_len {
+        let packages = items.chunks_exact(2).map(|pair| {
+            let mut symbols = pair[0].1.clone();
+            symbols.extend(&pair[1].1);
+            (pair[0].0 + pair[1].0, symbols)
+        });
+        let mut merged: Vec<(usize, Vec<usize>)> = leaves.iter().map(|(c, s)| (*c, vec![*s])).collect();
+        merged.extend(packages);
+        // stable, so that leaves come before packages of the same weight
+        merged.sort_by_key(|(weight, _)| *weight);
+        items = merged;
+    }
+
+    let mut lengths = vec![0; counts.len()];
+    for (_, symbols) in items.iter().take(2 * leaves.len() - 2) {
+        for symbol in symbols {
+            lengths[*symbol] += 1;
+        }
+    }
+    lengths
+}
+
+/// Code of each symbol given its code length, symbols of length 0 having none (0).
+pub fn codes(lengths: &[u8]) -> Vec<u32> {
+    let max_len = lengths.iter().cloned().max().unwrap_or(0) as usize;
+    let mut count = vec![0u32; max_len + 1];
+    for len in lengths {
+        count[*len as usize] += 1;
+    }
+    count[0] = 0;
+    let mut next = vec![0u32; max_len + 1];
+    let mut code = 0;
+    for len in 1..=max_len {
+        code = (code + count[len - 1]) << 1;
+        next[len] = code;
+    }
+    lengths
+        .iter()
+        .map(|len| match *len as usize {
+            0   => 0,
+            len => {
+                next[len] += 1;
+                next[len] - 1
+            },
+        })
+        .collect()
+}
+
+/// Decoder of a canonical code, which reads a code a bit at a time until it is
+/// below the end of the codes of its length, the codes of each length being consecutive.
+pub struct Decoder {
+    /// Number of codes of each length.
+    counts: Vec<usize>,
+    /// Symbols sorted by code.
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    /// Decoder of the code given by the length of each symbol, which may be incomplete
+    /// but not oversubscribed.
+    pub fn new(lengths: &[u8]) -> Result<Decoder> {
+        conversion::check_kraft(lengths.iter().filter(|len| **len > 0).map(|len| *len as usize))?;
+        let max_len = lengths.iter().cloned().max().unwrap_or(0) as usize;
+        let mut counts = vec![0; max_len + 1];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|s| lengths[*s as usize] > 0).collect();
+        symbols.sort_by_key(|s| lengths[*s as usize]);
+        Ok(Decoder { counts, symbols })
+    }
+
+    /// Read the code of one symbol, `next_bit` giving the bits of the code in order.
+    pub fn decode(&self, mut next_bit: impl FnMut() -> Result<u8>) -> Result<u16> {
+        // code read so far, and first code and index of the first symbol of its length
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for count in &self.counts[1..] {
+            code |= next_bit()? as usize;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::InvalidPayload("no code matches the payload".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_example() {
+        // RFC 1951 3.2.2: lengths (3, 3, 3, 3, 3, 2, 4, 4) for A to H
+        assert_eq!(codes(&[3, 3, 3, 3, 3, 2, 4, 4]), vec![0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111]);
+        assert_eq!(codes(&[0, 1, 0, 1]), vec![0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn decoder() {
+        let lengths = [3, 3, 3, 3, 3, 2, 4, 4];
+        let decoder = Decoder::new(&lengths).unwrap();
+        for (symbol, (code, len)) in codes(&lengths).iter().zip(&lengths).enumerate() {
+            let mut bits = (0..*len).rev().map(|i| (code >> i) as u8 & 1);
+            assert_eq!(decoder.decode(|| Ok(bits.next().unwrap())).unwrap(), symbol as u16);
+        }
+        // incomplete code, 1 has no symbol
+        let decoder = Decoder::new(&[0, 1]).unwrap();
+        assert_eq!(decoder.decode(|| Ok(0)).unwrap(), 1);
+        assert!(decoder.decode(|| Ok(1)).is_err());
+        assert!(Decoder::new(&[1, 1, 1]).is_err());
+        assert!(Decoder::new(&[0, 0]).unwrap().decode(|| Ok(0)).is_err());
+    }
+
+    #[test]
+    fn huffman_lengths() {
+        assert_eq!(lengths(&[5, 2, 1, 1], 15), vec![1, 2, 3, 3]);
+        assert_eq!(lengths(&[0, 7, 0], 15), vec![0, 1, 0]);
+        assert_eq!(lengths(&[0, 0], 15), vec![0, 0]);
+    }
+
+    #[test]
+    fn limited_lengths() {
+        // Fibonacci counts give a tree as deep as there are symbols
+        let counts = [1, 1, 2, 3, 5, 8, 13, 21, 34, 55];
+        assert_eq!(*lengths(&counts, 15).iter().max().unwrap(), 9);
+        let limited = lengths(&counts, 4);
+        assert!(limited.iter().all(|len| *len <= 4));
+        // the lengths are complete: the sum of 2^-len is 1
+        assert_eq!(limited.iter().map(|len| 1 << (4 - len)).sum::<u32>(), 16);
+        let cost = |lengths: &[u8]| counts.iter().zip(lengths).map(|(c, l)| c * *l as usize).sum::<usize>();
+        assert!(cost(&limited) > cost(&lengths(&counts, 15)));
+        // without a limit, as good as the Huffman coding
+        assert_eq!(cost(&package_merge(&counts, 9)), cost(&lengths(&counts, 15)));
+    }
+}
@@ -0,0 +1,143 @@
+//! CRC-32 as used by gzip and zip (IEEE 802.3 polynomial, reflected), and Adler-32 as used by zlib.
+
+use std::io::{self, Write};
+
+const POLYNOMIAL: u32 = 0xedb88320;
+
+fn table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut crc = i as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+        }
+        *entry = crc;
+    }
+    table
+}
+
+pub struct Crc32 {
+    table: [u32; 256],
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { table: table(), crc: 0xffffffff }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.crc = self.table[((self.crc ^ *b as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        self.crc ^ 0xffffffff
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Largest prime below 2^16, the modulus of the sums of Adler-32.
+const ADLER_MODULUS: u32 = 65521;
+
+/// Most bytes that can be summed before the sums of Adler-32 overflow 32 bits.
+const ADLER_CHUNK_SIZE: usize = 5552;
+
+/// Adler-32 of `data`: the sum of its bytes plus one, and the sum of those sums, modulo 65521.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(ADLER_CHUNK_SIZE) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= ADLER_MODULUS;
+        b %= ADLER_MODULUS;
+    }
+    b << 16 | a
+}
+
+/// Writer computing the checksum and size of what goes through it.
+pub struct CrcWriter<W: Write> {
+    inner: W,
+    crc: Crc32,
+    size: u64,
+}
+
+impl<W: Write> CrcWriter<W> {
+    pub fn new(inner: W) -> CrcWriter<W> {
+        CrcWriter { inner, crc: Crc32::new(), size: 0 }
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc.finish()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+
+    #[test]
+    fn adler() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a302c);
+    }
+
+    #[test]
+    fn writer() {
+        let mut w = CrcWriter::new(Vec::new());
+        w.write_all(b"123456789").unwrap();
+        assert_eq!(w.crc(), 0xcbf43926);
+        assert_eq!(w.size(), 9);
+        assert_eq!(w.into_inner(), b"123456789");
+    }
+}
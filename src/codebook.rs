@@ -0,0 +1,261 @@
+//! Textual export of code tables, as JSON or CSV.
+//!
+//! Each symbol is listed with its number of occurences, code length and code:
+//!
+//! ```text
+//! symbol,count,length,code
+//! 97,5,1,0
+//! 98,2,2,10
+//! ```
+
+use std::collections::HashMap;
+
+use super::bits::BitSet;
+use super::conversion::Table;
+use super::error::{Error, Result};
+use super::json;
+use super::tree::{self, Tree};
+
+pub struct Entry {
+    pub symbol: u8,
+    pub count: usize,
+    pub code: BitSet,
+}
+
+/// Code table with the counts it was built from, sorted by symbol.
+pub struct Codebook(pub Vec<Entry>);
+
+impl Codebook {
+    pub fn new(table: &Table, counter: &HashMap<u8, usize>) -> Codebook {
+        let mut entries: Vec<Entry> = table.0
+            .iter()
+            .map(|(k, v)| Entry {
+                symbol: *k,
+                count: counter.get(k).cloned().unwrap_or(0),
+                code: v.clone(),
+            })
+            .collect();
+        entries.sort_by_key(|e| e.symbol);
+        Codebook(entries)
+    }
+
+    pub fn from_data(data: &[u8]) -> Codebook {
+        let counter = tree::count(data);
+        if counter.is_empty() {
+            return Codebook(Vec::new());
+        }
+        Codebook::new(&Table::from_tree(&Tree::from_counts(&counter)), &counter)
+    }
+
+    pub fn to_table(&self) -> Result<Table> {
+        let mut table = Table::new();
+        for e in &self.0 {
+            if table.0.insert(e.symbol, e.code.clone()).is_some() {
+                return Err(Error::InvalidTable(format!("duplicate symbol {}", e.symbol)));
+            }
+        }
+        table.validate()?;
+        Ok(table)
+    }
+
+    pub fn counts(&self) -> HashMap<u8, usize> {
+        self.0.iter().map(|e| (e.symbol, e.count)).collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("symbol,count,length,code\n");
+        for e in &self.0 {
+            out.push_str(&format!("{},{},{},{}\n", e.symbol, e.count, e.code.len, e.code));
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self.0
+            .iter()
+            .map(|e| format!(
+                "    {{\"symbol\": {}, \"count\": {}, \"length\": {}, \"code\": \"{}\"}}",
+                e.symbol, e.count, e.code.len, e.code
+            ))
+            .collect();
+        format!("{{\"symbols\": [\n{}\n]}}\n", entries.join(",\n"))
+    }
+
+    pub fn from_csv(s: &str) -> Result<Codebook> {
+        let mut entries = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (i == 0 && line.starts_with("symbol")) {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            if fields.len() != 4 {
+                return Err(Error::Parse(format!("line {}: expected 4 fields", i + 1)));
+            }
+            let number = |field: &str| field
+                .parse::<usize>()
+                .map_err(|_| Error::Parse(format!("line {}: invalid number '{}'", i + 1, field)));
+            let symbol = number(fields[0])?;
+            if symbol > 0xff {
+                return Err(Error::Parse(format!("line {}: symbol {} is not a byte", i + 1, symbol)));
+            }
+            entries.push(Entry::parse(symbol as u8, number(fields[1])?, number(fields[2])?, fields[3])?);
+        }
+        Ok(Codebook(entries))
+    }
+
+    pub fn from_json(s: &str) -> Result<Codebook> {
+        let value = json::parse(s)?;
+        let symbols = value
+            .get("symbols")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| Error::Parse("expected a \"symbols\" array".to_string()))?;
+        let mut entries = Vec::new();
+        for v in symbols {
+            let field = |name: &str| v
+                .get(name)
+                .and_then(|f| f.as_u64())
+                .ok_or_else(|| Error::Parse(format!("expected an integer \"{}\" field", name)));
+            let symbol = field("symbol")?;
+            if symbol > 0xff {
+                return Err(Error::Parse(format!("symbol {} is not a byte", symbol)));
+            }
+            let code = v
+                .get("code")
+                .and_then(|f| f.as_str())
+                .ok_or_else(|| Error::Parse("expected a string \"code\" field".to_string()))?;
+            entries.push(Entry::parse(symbol as u8, field("count")? as usize, field("length")? as usize, code)?);
+        }
+        Ok(Codebook(entries))
+    }
+
+    /// Parse either format, JSON documents being recognized by their leading brace.
+    pub fn parse(s: &str) -> Result<Codebook> {
+        if s.trim_start().starts_with('{') {
+            Codebook::from_json(s)
+        } else {
+            Codebook::from_csv(s)
+        }
+    }
+}
+
+impl Entry {
+    fn parse(symbol: u8, count: usize, length: usize, code: &str) -> Result<Entry> {
+        let mut bits = BitSet::new();
+        for c in code.chars() {
+            match c {
+                '0' => bits.push_bit(0),
+                '1' => bits.push_bit(1),
+                _   => return Err(Error::Parse(format!("invalid code '{}' for symbol {}", code, symbol))),
+            }
+        }
+        if bits.len != length {
+            return Err(Error::Parse(format!(
+                "code '{}' of symbol {} is not {} bits long", code, symbol, length
+            )));
+        }
+        Ok(Entry { symbol, count, code: bits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"abracadabra, alakazam";
+
+    fn assert_same(a: &Codebook, b: &Codebook) {
+        assert_eq!(a.0.len(), b.0.len());
+        for (x, y) in a.0.iter().zip(b.0.iter()) {
+            assert_eq!((x.symbol, x.count), (y.symbol, y.count));
+            assert_eq!(x.code, y.code);
+        }
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let codebook = Codebook::from_data(DATA);
+        assert_same(&codebook, &Codebook::parse(&codebook.to_csv()).unwrap());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let codebook = Codebook::from_data(DATA);
+        assert_same(&codebook, &Codebook::parse(&codebook.to_json()).unwrap());
+    }
+
+    #[test]
+    fn to_table_valid() {
+        let codebook = Codebook::from_csv("symbol,count,length,code\n97,3,1,0\n98,1,2,10\n99,1,2,11\n").unwrap();
+        let table = codebook.to_table().unwrap();
+        assert_eq!(table.0[&b'b'].to_string(), "10");
+    }
+
+    #[test]
+    fn to_table_not_prefix_free() {
+        let codebook = Codebook::from_csv("97,3,1,1\n98,1,2,10\n").unwrap();
+        assert!(matches!(codebook.to_table(), Err(Error::InvalidTable(_))));
+    }
+
+    #[test]
+    fn to_table_duplicate() {
+        let codebook = Codebook::from_csv("97,3,1,1\n97,1,1,0\n").unwrap();
+        assert!(matches!(codebook.to_table(), Err(Error::InvalidTable(_))));
+    }
+
+    #[test]
+    fn to_table_too_long() {
+        let csv = format!("97,3,1,0\n98,1,300,1{}\n", "0".repeat(299));
+        let codebook = Codebook::from_csv(&csv).unwrap();
+        assert!(matches!(codebook.to_table(), Err(Error::InvalidTable(_))));
+    }
+
+    fn error(result: Result<Codebook>) -> String {
+        match result {
+            Err(Error::Parse(message)) => message,
+            Err(e)                     => panic!("unexpected error {}", e),
+            Ok(_)                      => panic!("parsed"),
+        }
+    }
+
+    #[test]
+    fn csv_errors() {
+        assert_eq!(error(Codebook::from_csv("97,3,1\n")), "line 1: expected 4 fields");
+        assert_eq!(error(Codebook::from_csv("symbol,count,length,code\n97,x,1,0\n")), "line 2: invalid number 'x'");
+        assert_eq!(error(Codebook::from_csv("256,3,1,1\n")), "line 1: symbol 256 is not a byte");
+        // the header is only skipped on the first line
+        assert_eq!(error(Codebook::from_csv("97,3,1,0\nsymbol,count,length,code\n")), "line 2: invalid number 'symbol'");
+    }
+
+    #[test]
+    fn json_errors() {
+        assert_eq!(error(Codebook::from_json("[]")), "expected a \"symbols\" array");
+        assert_eq!(error(Codebook::from_json("{\"symbols\": {}}")), "expected a \"symbols\" array");
+        assert_eq!(
+            error(Codebook::from_json("{\"symbols\": [{\"symbol\": 97, \"code\": \"0\"}]}")),
+            "expected an integer \"count\" field"
+        );
+        assert_eq!(
+            error(Codebook::from_json("{\"symbols\": [{\"symbol\": -1, \"count\": 1}]}")),
+            "expected an integer \"symbol\" field"
+        );
+        assert_eq!(
+            error(Codebook::from_json("{\"symbols\": [{\"symbol\": 300, \"count\": 1, \"length\": 1}]}")),
+            "symbol 300 is not a byte"
+        );
+        assert_eq!(
+            error(Codebook::from_json("{\"symbols\": [{\"symbol\": 97, \"count\": 1, \"length\": 1, \"code\": 0}]}")),
+            "expected a string \"code\" field"
+        );
+    }
+
+    #[test]
+    fn code_errors() {
+        assert_eq!(error(Codebook::from_csv("97,3,1,2\n")), "invalid code '2' for symbol 97");
+        assert_eq!(error(Codebook::from_csv("97,3,2,1\n")), "code '1' of symbol 97 is not 2 bits long");
+        assert_eq!(
+            error(Codebook::parse("{\"symbols\": [{\"symbol\": 98, \"count\": 1, \"length\": 1, \"code\": \"01\"}]}")),
+            "code '01' of symbol 98 is not 1 bits long"
+        );
+    }
+}
@@ -0,0 +1,789 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::bits::{BitReader, BitWriter};
+use super::bwt;
+use super::checksum::{self, Crc32, CrcWriter};
+use super::conversion::{self, Table};
+use super::error::{Error, Result};
+use super::lz77;
+use super::metadata::Metadata;
+use super::range;
+use super::rle;
+use super::tans;
+use super::tree::Tree;
+
+pub const MAGIC: &[u8; 4] = b"HUFF";
+pub const SUFFIX: &str = ".huffman";
+
+/// Size of the chunks the input is read by when streaming.
+const CHUNK_SIZE: u64 = 1 << 16;
+
+/// Size of the container header for a given table, without metadata.
+pub fn header_size(table: &Table) -> usize {
+    MAGIC.len() + Metadata::default().serialize().len() + 1 + table.serialize().len() + 8 + 4
+}
+
+/// How the payload of a compressed file is coded, stored in the byte following the metadata.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Huffman = 0,
+    /// Data copied as is, when coding it would make it larger.
+    Stored = 1,
+    /// Run-length encoding followed by Huffman coding, see `rle`.
+    Rle = 2,
+    /// Burrows–Wheeler transform and move-to-front followed by Huffman coding, see `bwt`.
+    Bwt = 3,
+    /// LZ77 followed by Huffman coding, see `lz77`.
+    Lz77 = 4,
+    /// Range coding of the bytes with the counts of the Huffman coding, see `range`.
+    Range = 5,
+    /// Table-based asymmetric numeral systems with the counts of the Huffman coding, see `tans`.
+    Tans = 6,
+}
+
+impl Method {
+    pub const ALL: [Method; 7] = [
+        Method::Huffman, Method::Stored, Method::Rle, Method::Bwt, Method::Lz77, Method::Range, Method::Tans,
+    ];
+
+    pub fn from_byte(byte: u8) -> Result<Method> {
+        Method::ALL
+            .iter()
+            .find(|m| **m as u8 == byte)
+            .cloned()
+            .ok_or_else(|| Error::InvalidHeader(format!("unknown method {}", byte)))
+    }
+
+    pub fn from_name(name: &str) -> Option<Method> {
+        Method::ALL.iter().find(|m| m.name() == name).cloned()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Huffman => "huffman",
+            Method::Stored  => "stored",
+            Method::Rle     => "rle",
+            Method::Bwt     => "bwt",
+            Method::Lz77    => "lz77",
+            Method::Range   => "range",
+            Method::Tans    => "tans",
+        }
+    }
+
+    /// Whether the data is coded a chunk at a time, other methods code all of it at once.
+    pub fn is_streamed(self) -> bool {
+        match self {
+            Method::Huffman | Method::Stored => true,
+            Method::Rle | Method::Bwt | Method::Lz77 | Method::Range | Method::Tans => false,
+        }
+    }
+
+    /// Error for a streamed method given to `encode` or `decode`.
+    fn streamed_error(self) -> Error {
+        Error::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is coded a chunk at a time", self.name())))
+    }
+
+    /// Code all of `data`, for a method that is not streamed.
+    fn encode(self, data: &[u8], options: &Options) -> Result<Vec<u8>> {
+        match self {
+            Method::Huffman | Method::Stored => Err(self.streamed_error()),
+            Method::Rle   => Ok(rle::encode(data)),
+            Method::Bwt   => Ok(bwt::encode(data)),
+            Method::Lz77  => Ok(lz77::encode_with_window(data, options.window.unwrap_or(lz77::WINDOW_SIZE))),
+            Method::Range => Ok(range::encode(data)),
+            Method::Tans  => Ok(tans::encode(data)),
+        }
+    }
+
+    /// Decode a payload coded by `encode` into `out`.
+    fn decode<W: Write>(self, payload: &[u8], out: &mut W) -> Result<()> {
+        match self {
+            Method::Huffman | Method::Stored => return Err(self.streamed_error()),
+            Method::Rle   => out.write_all(&rle::decode(payload)?)?,
+            Method::Bwt   => out.write_all(&bwt::decode(payload)?)?,
+            Method::Lz77  => out.write_all(&lz77::decode(payload)?)?,
+            Method::Range => range::decode_to(payload, out)?,
+            Method::Tans  => tans::decode_to(payload, out)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Options<'a> {
+    /// Table to use instead of one built from the data.
+    pub table: Option<&'a Table>,
+    pub metadata: Metadata,
+    /// Method to use, by default the Huffman coding unless storing the data is smaller.
+    /// The table is only used by the Huffman coding.
+    pub method: Option<Method>,
+    /// Size of the window of `Method::Lz77`, `lz77::WINDOW_SIZE` by default.
+    pub window: Option<usize>,
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    compress_with(data, &Options::default()).unwrap()
+}
+
+/// Compress with a given table instead of one built from the data.
+pub fn compress_with_table(data: &[u8], table: &Table) -> Result<Vec<u8>> {
+    compress_with(data, &Options { table: Some(table), ..Options::default() })
+}
+
+pub fn compress_with(data: &[u8], options: &Options) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() / 2 + 64);
+    compress_stream(io::Cursor::new(data), &mut out, options)?;
+    Ok(out)
+}
+
+/// Read `buffer.len()` bytes, reporting a missing one as `Error::UnexpectedEof`.
+pub(crate) fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<()> {
+    reader.read_exact(buffer).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+        _                            => Error::Io(e),
+    })
+}
+
+/// Read the next chunk of `reader` into `buffer`, returns false at the end of the reader.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut Vec<u8>) -> io::Result<bool> {
+    buffer.clear();
+    reader.take(CHUNK_SIZE).read_to_end(buffer)?;
+    Ok(!buffer.is_empty())
+}
+
+/// Count the occurences of each byte of `reader`, returns them with its checksum and size.
+fn scan<R: Read>(reader: &mut R) -> Result<(HashMap<u8, usize>, u32, u64)> {
+    let mut counts = [0usize; 256];
+    let mut crc = Crc32::new();
+    let mut size = 0;
+    let mut buffer = Vec::with_capacity(CHUNK_SIZE as usize);
+    while read_chunk(reader, &mut buffer)? {
+        for b in &buffer {
+            counts[*b as usize] += 1;
+        }
+        crc.update(&buffer);
+        size += buffer.len() as u64;
+    }
+    let counter = (0..=255u8)
+        .filter(|b| counts[*b as usize] != 0)
+        .map(|b| (b, counts[b as usize]))
+        .collect();
+    Ok((counter, crc.finish(), size))
+}
+
+/// Compress what is read from `input` into `output`.
+///
+/// The input is read twice, once to build the table and once to encode it,
+/// so that only a chunk of it is in memory at a time.
+/// This only holds for the streamed methods, `Method::Huffman` and `Method::Stored`:
+/// the others read the whole input in memory and code it at once.
+pub fn compress_stream<R: Read + Seek, W: Write>(mut input: R, mut output: W, options: &Options) -> Result<()> {
+    if let Some(method) = options.method.filter(|m| !m.is_streamed()) {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let payload = method.encode(&data, options)?;
+        output.write_all(MAGIC)?;
+        output.write_all(&options.metadata.serialize())?;
+        output.write_all(&[method as u8])?;
+        output.write_all(&(payload.len() as u64 * 8).to_be_bytes())?;
+        output.write_all(&checksum::crc32(&data).to_be_bytes())?;
+        output.write_all(&payload)?;
+        output.flush()?;
+        return Ok(());
+    }
+    let start = input.stream_position()?;
+    let (counter, crc, size) = scan(&mut input)?;
+    let built_table;
+    let table = match options.table {
+        Some(table) => {
+            table.validate()?;
+            if let Some(b) = counter.keys().find(|b| !table.0.contains_key(b)) {
+                return Err(Error::InvalidTable(format!("no code for byte {}", b)));
+            }
+            table
+        },
+        None => {
+            built_table = if counter.is_empty() {
+                Table::new()
+            } else {
+                Table::from_tree(&Tree::from_counts(&counter))
+            };
+            &built_table
+        },
+    };
+    let bits_len: u64 = counter.iter().map(|(k, v)| *v as u64 * table.0[k].len as u64).sum();
+    let serialized_table = table.serialize();
+    let method = options.method.unwrap_or(
+        if serialized_table.len() as u64 + bits_len.div_ceil(8) < size { Method::Huffman } else { Method::Stored }
+    );
+
+    output.write_all(MAGIC)?;
+    output.write_all(&options.metadata.serialize())?;
+    output.write_all(&[method as u8])?;
+    if method == Method::Huffman {
+        output.write_all(&serialized_table)?;
+        output.write_all(&bits_len.to_be_bytes())?;
+    } else {
+        output.write_all(&(size * 8).to_be_bytes())?;
+    }
+    output.write_all(&crc.to_be_bytes())?;
+
+    input.seek(SeekFrom::Start(start))?;
+    let mut writer = BitWriter::new(output);
+    let mut buffer = Vec::with_capacity(CHUNK_SIZE as usize);
+    let mut written_size = 0;
+    while read_chunk(&mut input, &mut buffer)? {
+        match method {
+            Method::Huffman => table.encode_to(&buffer, &mut writer)?,
+            _               => writer.write_bytes(&buffer)?,
+        }
+        written_size += buffer.len() as u64;
+    }
+    let written_bits = if method == Method::Huffman { bits_len } else { size * 8 };
+    if written_size != size || writer.len() != written_bits {
+        return Err(Error::Io(io::Error::other("input changed while being compressed")));
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Append the table, payload size, checksum and payload of `data` coded with `table`,
+/// that is everything but the magic bytes and metadata of a compressed file.
+pub fn write_stream(table: &Table, data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    out.extend(table.serialize());
+    write_payload(table, data, out)
+}
+
+/// Append the payload size, checksum and payload of `data` coded with `table`.
+pub fn write_payload(table: &Table, data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let mut writer = BitWriter::new(Vec::new());
+    table.encode_to(data, &mut writer)?;
+    out.extend_from_slice(&writer.len().to_be_bytes());
+    out.extend_from_slice(&checksum::crc32(data).to_be_bytes());
+    out.extend(writer.finish()?);
+    Ok(())
+}
+
+pub struct Header {
+    pub method: Method,
+    /// Empty unless the method is `Method::Huffman`.
+    pub table: Table,
+    pub bits_len: u64,
+    pub crc: u32,
+}
+
+/// Parse what `write_stream` wrote at the start of `data`,
+/// returns the header, the payload and the data following the payload.
+pub fn read_stream(data: &[u8]) -> Result<(Header, &[u8], &[u8])> {
+    let (table, table_size) = Table::deserialize(data)?;
+    read_payload(table, &data[table_size..])
+}
+
+/// Parse what `write_payload` wrote at the start of `data`, for a payload coded with `table`.
+pub fn read_payload(table: Table, data: &[u8]) -> Result<(Header, &[u8], &[u8])> {
+    if data.len() < 12 {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut bits_len = [0; 8];
+    bits_len.copy_from_slice(&data[..8]);
+    let bits_len = u64::from_be_bytes(bits_len);
+    let crc = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    let data = &data[12..];
+    if bits_len.div_ceil(8) > data.len() as u64 {
+        return Err(Error::UnexpectedEof);
+    }
+    let payload_size = bits_len.div_ceil(8) as usize;
+    let header = Header { method: Method::Huffman, table, bits_len, crc };
+    Ok((header, &data[..payload_size], &data[payload_size..]))
+}
+
+/// Decode a payload into `out`, returns the size of the decoded data.
+pub fn decode_stream<W: Write>(header: &Header, payload: &[u8], out: &mut W) -> Result<u64> {
+    decode_from(payload, header, out)
+}
+
+/// Decode the payload read from `reader` into `out`, returns the size of the decoded data.
+/// The reader should end with the payload.
+pub fn decode_from<R: Read, W: Write>(mut reader: R, header: &Header, out: W) -> Result<u64> {
+    let mut out = CrcWriter::new(out);
+    match header.method {
+        Method::Huffman => {
+            let mut reader = BitReader::new(reader);
+            header.table.decode_from(&mut reader, header.bits_len, &mut out)?;
+            if reader.remaining_in_byte() != 0 {
+                return Err(Error::InvalidPayload("padding bits are not zero".to_string()));
+            }
+            if reader.has_more_bytes()? {
+                return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+            }
+        },
+        method => {
+            if !header.bits_len.is_multiple_of(8) {
+                return Err(Error::InvalidHeader("payload is not whole bytes".to_string()));
+            }
+            let size = header.bits_len / 8;
+            if method == Method::Stored {
+                if io::copy(&mut (&mut reader).take(size), &mut out)? != size {
+                    return Err(Error::UnexpectedEof);
+                }
+            } else {
+                // grows with what is read rather than with the size in the header
+                let mut payload = Vec::new();
+                if (&mut reader).take(size).read_to_end(&mut payload)? as u64 != size {
+                    return Err(Error::UnexpectedEof);
+                }
+                method.decode(&payload, &mut out)?;
+            }
+            if reader.read(&mut [0])? != 0 {
+                return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+            }
+        },
+    }
+    if out.crc() != header.crc {
+        return Err(Error::ChecksumMismatch { expected: header.crc, actual: out.crc() });
+    }
+    Ok(out.size())
+}
+
+/// Parse the header of a compressed file, returns it with the payload that follows.
+pub fn read_header(data: &[u8]) -> Result<(Metadata, Header, &[u8])> {
+    if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    let data = &data[MAGIC.len()..];
+    let (metadata, metadata_size) = Metadata::deserialize(data)?;
+    let method = Method::from_byte(*data.get(metadata_size).ok_or(Error::UnexpectedEof)?)?;
+    let data = &data[metadata_size + 1..];
+    let (header, payload, rest) = match method {
+        Method::Huffman => read_stream(data)?,
+        _ => {
+            let (header, payload, rest) = read_payload(Table::new(), data)?;
+            (Header { method, ..header }, payload, rest)
+        },
+    };
+    if !rest.is_empty() {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    Ok((metadata, header, payload))
+}
+
+/// Read the header of a compressed file from `reader`, which is left at the start of the payload.
+pub fn read_header_from<R: Read>(reader: &mut R) -> Result<(Metadata, Header)> {
+    let mut magic = [0; 4];
+    read_exact(reader, &mut magic).map_err(|_| Error::InvalidMagic)?;
+    if &magic != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    let metadata = Metadata::read_from(reader)?;
+    let mut method = [0];
+    read_exact(reader, &mut method)?;
+    let method = Method::from_byte(method[0])?;
+
+    let table = if method == Method::Huffman {
+        let mut table = vec![0; 4];
+        read_exact(reader, &mut table)?;
+        let table_size = u32::from_be_bytes([table[0], table[1], table[2], table[3]]) as usize;
+        if table_size > conversion::MAX_SERIALIZED_SIZE {
+            return Err(Error::InvalidHeader(format!("table size {} is too large", table_size)));
+        }
+        table.resize(4 + table_size, 0);
+        read_exact(reader, &mut table[4..])?;
+        Table::deserialize(&table)?.0
+    } else {
+        Table::new()
+    };
+
+    let mut fields = [0; 12];
+    read_exact(reader, &mut fields)?;
+    let mut bits_len = [0; 8];
+    bits_len.copy_from_slice(&fields[..8]);
+    let bits_len = u64::from_be_bytes(bits_len);
+    let crc = u32::from_be_bytes([fields[8], fields[9], fields[10], fields[11]]);
+    Ok((metadata, Header { method, table, bits_len, crc }))
+}
+
+/// Check `size` bytes were decoded if `metadata` has the original size.
+pub fn check_size(metadata: &Metadata, size: u64) -> Result<()> {
+    match metadata.size {
+        Some(expected) if expected != size => Err(Error::InvalidPayload(format!(
+            "decoded {} bytes but the header says {}", size, expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    decompress_to(data, &mut content)?;
+    Ok(content)
+}
+
+/// Decompress, returns the data with the metadata stored with it.
+pub fn decompress_with_metadata(data: &[u8]) -> Result<(Vec<u8>, Metadata)> {
+    let mut content = Vec::new();
+    let metadata = decompress_to(data, &mut content)?;
+    Ok((content, metadata))
+}
+
+/// Decompress into `out`, returns the metadata stored with the data.
+pub fn decompress_to<W: Write>(data: &[u8], out: &mut W) -> Result<Metadata> {
+    decompress_stream(data, out)
+}
+
+/// Decompress what is read from `reader` into `out`, one chunk at a time,
+/// returns the metadata stored with the data.
+pub fn decompress_stream<R: Read, W: Write>(mut reader: R, out: W) -> Result<Metadata> {
+    let (metadata, header) = read_header_from(&mut reader)?;
+    let size = decode_from(reader, &header, out)?;
+    check_size(&metadata, size)?;
+    Ok(metadata)
+}
+
+/// Outcome of a successful `verify`.
+pub struct Verified {
+    pub compressed_size: u64,
+    pub size: u64,
+    pub crc: u32,
+}
+
+/// Reader counting the bytes read through it.
+struct CountingReader<R: Read> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// Fully decode what is read from `reader` without keeping the result,
+/// checking the header, payload and checksum are consistent.
+pub fn verify<R: Read>(reader: R) -> Result<Verified> {
+    let mut reader = CountingReader { inner: reader, count: 0 };
+    let (metadata, header) = read_header_from(&mut reader)?;
+    let size = decode_from(&mut reader, &header, io::sink())?;
+    check_size(&metadata, size)?;
+    Ok(Verified { compressed_size: reader.count, size, crc: header.crc })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bench::Lcg;
+
+    /// Compress with the Huffman coding even if storing the data would be smaller.
+    fn huffman(data: &[u8]) -> Vec<u8> {
+        compress_with(data, &Options { method: Some(Method::Huffman), ..Options::default() }).unwrap()
+    }
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        round_trip(b"");
+    }
+
+    #[test]
+    fn round_trip_one_symbol() {
+        round_trip(b"aaaaaaaaaaa");
+    }
+
+    #[test]
+    fn round_trip_text() {
+        round_trip(b"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+tempor incididunt ut labore et dolore magna aliqua.");
+    }
+
+    #[test]
+    fn round_trip_all_bytes() {
+        let data: Vec<u8> = (0..=255).chain(0..128).chain(0..16).collect();
+        round_trip(&data);
+    }
+
+    #[test]
+    fn compress_with_other_table() {
+        let table = Table::from_tree(&Tree::from_data(b"abcdefgh"));
+        let compressed = compress_with_table(b"badcafe", &table).unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), b"badcafe");
+        assert!(compress_with_table(b"xyz", &table).is_err());
+    }
+
+    #[test]
+    fn metadata() {
+        let metadata = Metadata {
+            name: Some("abra.txt".to_string()),
+            mtime: Some(1_000_000),
+            mode: Some(0o600),
+            size: Some(11),
+        };
+        let options = Options { metadata: metadata.clone(), ..Options::default() };
+        let compressed = compress_with(b"abracadabra", &options).unwrap();
+        assert_eq!(decompress_with_metadata(&compressed).unwrap(), (b"abracadabra".to_vec(), metadata));
+    }
+
+    #[test]
+    fn metadata_wrong_size() {
+        let metadata = Metadata { size: Some(12), ..Metadata::default() };
+        let options = Options { metadata, ..Options::default() };
+        let compressed = compress_with(b"abracadabra", &options).unwrap();
+        assert!(matches!(decompress(&compressed), Err(Error::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn stored_when_smaller() {
+        let data: Vec<u8> = (0..=255).collect();
+        let compressed = compress(&data);
+        assert_eq!(read_header(&compressed).unwrap().1.method, Method::Stored);
+        // no table, nor its size
+        assert_eq!(compressed.len(), header_size(&Table::new()) - 4 + data.len());
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        assert_eq!(read_header(&compress(&[b'a'; 100])).unwrap().1.method, Method::Huffman);
+    }
+
+    #[test]
+    fn forced_method() {
+        for method in Method::ALL {
+            let options = Options { method: Some(method), ..Options::default() };
+            let compressed = compress_with(b"abracadabra", &options).unwrap();
+            assert_eq!(read_header(&compressed).unwrap().1.method, method);
+            assert_eq!(decompress(&compressed).unwrap(), b"abracadabra");
+        }
+    }
+
+    #[test]
+    fn lz77_window() {
+        // repeated 1000 bytes back, out of reach of a window of 16 bytes
+        let data = crate::bench::random(1000).repeat(2);
+        let compress = |window| {
+            let options = Options { method: Some(Method::Lz77), window, ..Options::default() };
+            compress_with(&data, &options).unwrap()
+        };
+        let (default, small) = (compress(None), compress(Some(16)));
+        assert!(default.len() < small.len() - 500);
+        assert_eq!(decompress(&default).unwrap(), data);
+        assert_eq!(decompress(&small).unwrap(), data);
+    }
+
+    #[test]
+    fn streamed_methods_are_not_coded_at_once() {
+        for method in Method::ALL {
+            assert_eq!(method.encode(b"abc", &Options::default()).is_err(), method.is_streamed());
+            if method.is_streamed() {
+                assert!(method.decode(b"abc", &mut Vec::new()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn stored_errors() {
+        let options = Options { method: Some(Method::Stored), ..Options::default() };
+        let compressed = compress_with(b"abracadabra", &options).unwrap();
+        assert!(matches!(decompress(&compressed[..compressed.len() - 1]), Err(Error::UnexpectedEof)));
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+        assert!(matches!(decompress(&trailing), Err(Error::InvalidPayload(_))));
+        let mut corrupted = compressed;
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(decompress(&corrupted), Err(Error::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn unknown_method() {
+        let mut compressed = compress(b"abracadabra");
+        compressed[MAGIC.len() + 1] = 0x7f;
+        assert!(matches!(decompress(&compressed), Err(Error::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn invalid_magic() {
+        assert!(matches!(decompress(b"HUFX"), Err(Error::InvalidMagic)));
+    }
+
+    #[test]
+    fn verify_valid() {
+        let compressed = huffman(b"abracadabra");
+        let verified = verify(&compressed[..]).unwrap();
+        assert_eq!(verified.size, 11);
+        assert_eq!(verified.crc, checksum::crc32(b"abracadabra"));
+    }
+
+    #[test]
+    fn verify_checksum_mismatch() {
+        let mut compressed = huffman(b"abracadabra");
+        let crc_offset = header_size(&read_header(&compressed).unwrap().1.table) - 4;
+        compressed[crc_offset] ^= 1;
+        assert!(matches!(verify(&compressed[..]), Err(Error::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn verify_padding() {
+        let mut compressed = huffman(b"abracadabra");
+        *compressed.last_mut().unwrap() |= 1;
+        assert!(matches!(verify(&compressed[..]), Err(Error::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn truncated() {
+        let compressed = huffman(b"abracadabra");
+        assert!(decompress(&compressed[..compressed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn verify_truncated() {
+        let compressed = huffman(b"abracadabra");
+        let header_size = header_size(&read_header(&compressed).unwrap().1.table);
+        // in the payload, then in the header
+        assert!(matches!(verify(&compressed[..compressed.len() - 1]), Err(Error::UnexpectedEof)));
+        assert!(matches!(verify(&compressed[..header_size - 1]), Err(Error::UnexpectedEof)));
+    }
+
+    #[test]
+    fn verify_wrong_size() {
+        let metadata = Metadata { size: Some(12), ..Metadata::default() };
+        let compressed = compress_with(b"abracadabra", &Options { metadata, ..Options::default() }).unwrap();
+        match verify(&compressed[..]) {
+            Err(Error::InvalidPayload(message)) => assert_eq!(message, "decoded 11 bytes but the header says 12"),
+            _ => panic!("wrong size verified"),
+        }
+    }
+
+    #[test]
+    fn verify_trailing_bytes() {
+        let mut compressed = huffman(b"abracadabra");
+        assert_eq!(verify(&compressed[..]).unwrap().compressed_size, compressed.len() as u64);
+        compressed.push(0);
+        assert!(matches!(verify(&compressed[..]), Err(Error::InvalidPayload(_))));
+    }
+
+    /// Decoding and inspecting `data` should fail or succeed, never panic.
+    fn check_no_panic(data: &[u8]) {
+        let _ = decompress(data);
+        let _ = verify(data);
+        let _ = crate::inspect::inspect(data);
+    }
+
+    #[test]
+    fn malformed_input_does_not_panic() {
+        let mut rng = Lcg(1);
+        let mut random = || rng.next_u32();
+        for method in Method::ALL {
+            let metadata = Metadata { name: Some("abra".to_string()), size: Some(22), ..Metadata::default() };
+            let options = Options { metadata, method: Some(method), ..Options::default() };
+            let valid = compress_with(b"abracadabra  alakazaam", &options).unwrap();
+            for end in 0..valid.len() {
+                check_no_panic(&valid[..end]);
+            }
+            for _ in 0..1000 {
+                let mut data = valid.clone();
+                for _ in 0..1 + random() % 4 {
+                    let i = random() as usize % data.len();
+                    data[i] = random() as u8;
+                }
+                check_no_panic(&data);
+            }
+        }
+        for _ in 0..500 {
+            let mut data = MAGIC.to_vec();
+            data.extend((0..random() % 64).map(|_| random() as u8));
+            check_no_panic(&data);
+        }
+    }
+
+    /// `len` bytes cycling through `pattern`, without storing them.
+    struct Repeat {
+        pattern: &'static [u8],
+        len: u64,
+        position: u64,
+    }
+
+    impl Read for Repeat {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.len.saturating_sub(self.position) as usize);
+            for (i, b) in buf[..n].iter_mut().enumerate() {
+                *b = self.pattern[((self.position + i as u64) % self.pattern.len() as u64) as usize];
+            }
+            self.position += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for Repeat {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let position = match pos {
+                SeekFrom::Start(position)  => Some(position),
+                SeekFrom::Current(offset)  => self.position.checked_add_signed(offset),
+                SeekFrom::End(offset)      => self.len.checked_add_signed(offset),
+            };
+            self.position = position
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn stream_several_chunks() {
+        let len = 3 * CHUNK_SIZE + 5;
+        let mut compressed = Vec::new();
+        let input = Repeat { pattern: b"abracadabra", len, position: 0 };
+        compress_stream(input, &mut compressed, &Options::default()).unwrap();
+        let data: Vec<u8> = b"abracadabra".iter().cycle().take(len as usize).cloned().collect();
+        let mut content = Vec::new();
+        decompress_stream(&compressed[..], &mut content).unwrap();
+        assert!(content == data);
+    }
+
+    fn large_header(bits_len: u64) -> Vec<u8> {
+        let table = Table::from_tree(&Tree::from_data(b"ab"));
+        let metadata = Metadata { size: Some(bits_len), ..Metadata::default() };
+        let mut data = MAGIC.to_vec();
+        data.extend(metadata.serialize());
+        data.push(Method::Huffman as u8);
+        data.extend(table.serialize());
+        data.extend_from_slice(&bits_len.to_be_bytes());
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn header_above_u32() {
+        let bits_len = u32::MAX as u64 * 3;
+        let data = large_header(bits_len);
+        let (metadata, header) = read_header_from(&mut &data[..]).unwrap();
+        assert_eq!(metadata.size, Some(bits_len));
+        assert_eq!(header.bits_len, bits_len);
+    }
+
+    #[test]
+    fn truncated_above_u32() {
+        let mut data = large_header(u32::MAX as u64 * 3);
+        data.extend(&[0x55; 16]);
+        assert!(matches!(decompress(&data), Err(Error::UnexpectedEof)));
+        assert!(matches!(read_header(&data), Err(Error::UnexpectedEof)));
+    }
+
+    /// Run with `cargo test --release -- --ignored`, takes a few minutes.
+    #[test]
+    #[ignore]
+    fn round_trip_above_u32() {
+        let len = u32::MAX as u64 + 1000;
+        let path = std::env::temp_dir().join(format!("huffman-large-{}", std::process::id()));
+        let input = Repeat { pattern: b"aaaaaaab", len, position: 0 };
+        let metadata = Metadata { size: Some(len), ..Metadata::default() };
+        let options = Options { metadata, ..Options::default() };
+        let file = std::fs::File::create(&path).unwrap();
+        compress_stream(input, io::BufWriter::new(file), &options).unwrap();
+
+        let file = io::BufReader::new(std::fs::File::open(&path).unwrap());
+        let verified = verify(file);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(verified.unwrap().size, len);
+    }
+}
@@ -1,43 +1,312 @@
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{Read, Write};
 
-use super::bits::BitSet;
+use super::bits::{BitReader, BitSet, BitWriter};
+use super::error::{Error, Result};
 use super::tree::Tree;
 
-pub struct Table(pub HashMap<u8, BitSet>);
+pub(crate) const DECODE_BUFFER_SIZE: usize = 1 << 16;
 
-impl Table {
-    pub fn new() -> Table {
+/// Largest serialized table, without its size field: every byte with a 255 bits code.
+pub const MAX_SERIALIZED_SIZE: usize = 256 * (2 + 32);
+
+/// Symbol of a table, written big endian in `SIZE` bytes when the table is serialized.
+pub trait Symbol: Copy + Eq + Hash {
+    const SIZE: usize;
+
+    fn write(self, out: &mut Vec<u8>);
+
+    fn read(bytes: &[u8]) -> Self;
+
+    /// How the symbol is shown in error messages.
+    fn name(self) -> String;
+}
+
+impl Symbol for u8 {
+    const SIZE: usize = 1;
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.push(self);
+    }
+
+    fn read(bytes: &[u8]) -> u8 {
+        bytes[0]
+    }
+
+    fn name(self) -> String {
+        format!("{:?}", self as char)
+    }
+}
+
+impl Symbol for u16 {
+    const SIZE: usize = 2;
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn read(bytes: &[u8]) -> u16 {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn name(self) -> String {
+        self.to_string()
+    }
+}
+
+/// Code of each symbol, bytes unless another `Symbol` type is given.
+#[derive(Clone, Default)]
+pub struct Table<S = u8>(pub HashMap<S, BitSet>);
+
+impl<S: Symbol> Table<S> {
+    pub fn new() -> Table<S> {
         Table(HashMap::new())
     }
 
-    pub fn from_tree(tree: &Tree) -> Table {
-        Table(tree.to_hash_map())
+    pub fn from_tree(tree: &Tree<S>) -> Table<S> {
+        let mut hm = tree.to_hash_map();
+        // a tree made of a single leaf would give it an empty code
+        if hm.len() == 1 {
+            for bits in hm.values_mut() {
+                bits.push_front_bit(0);
+            }
+        }
+        Table(hm)
     }
 
-    pub fn convert(&self, data: Vec<u8>) -> Vec<u8> {
-        let mut bitset = BitSet::new();
-        for byte in data {
-            bitset.concat(&self.0[&byte]);
+    /// Table of the Huffman code of `symbols`, empty without any.
+    pub fn from_symbols(symbols: impl Iterator<Item = S>) -> Table<S> {
+        let mut counter = HashMap::new();
+        for symbol in symbols {
+            *counter.entry(symbol).or_insert(0) += 1;
         }
-        bitset.data
+        if counter.is_empty() {
+            return Table::new();
+        }
+        Table::from_tree(&Tree::from_counts(&counter))
     }
 
     pub fn serialize(&self) -> Vec<u8> {
         let mut out: Vec<u8> = Vec::new();
         let size: u32 = self.0.iter()
-            .fold(0, |acc, (_, v)| acc + 2 + v.data.len() as u32);
+            .fold(0, |acc, (_, v)| acc + S::SIZE as u32 + 1 + v.data.len() as u32);
         out.push(((size & 0xff000000) >> 24) as u8);
         out.push(((size & 0x00ff0000) >> 16) as u8);
         out.push(((size & 0x0000ff00) >> 8) as u8);
-        out.push(((size & 0x000000ff) >> 0) as u8);
+        out.push((size & 0x000000ff) as u8);
 
         for (k, v) in &self.0 {
-            out.push(*k);
+            k.write(&mut out);
             out.push(v.len as u8);
             out.extend(v.data.iter());
         }
         out
     }
+
+    /// Check that the table can be decoded and serialized, that is no code is empty,
+    /// longer than 255 bits or the prefix of another.
+    pub fn validate(&self) -> Result<()> {
+        for (k, v) in &self.0 {
+            if v.len > u8::MAX as usize {
+                return Err(Error::InvalidTable(format!(
+                    "code of {} bits for {} is longer than {} bits", v.len, k.name(), u8::MAX
+                )));
+            }
+        }
+        self.check_kraft()?;
+        Trie::new(self).map(|_| ())
+    }
+
+    fn check_kraft(&self) -> Result<()> {
+        check_kraft(self.0.values().map(|code| code.len))
+    }
+
+    /// Parse a table written by `serialize` at the start of `data`,
+    /// returns it with the number of bytes read.
+    pub fn deserialize(data: &[u8]) -> Result<(Table<S>, usize)> {
+        if data.len() < 4 {
+            return Err(Error::UnexpectedEof);
+        }
+        let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        // every symbol with a 255 bits code
+        if size > (1 << (8 * S::SIZE)) * (S::SIZE + 1 + 32) {
+            return Err(Error::InvalidHeader(format!("table size {} is too large", size)));
+        }
+        let end = 4 + size;
+        if data.len() < end {
+            return Err(Error::UnexpectedEof);
+        }
+        let mut table = Table::new();
+        let mut i = 4;
+        while i < end {
+            if i + S::SIZE + 1 > end {
+                return Err(Error::InvalidHeader("truncated table entry".to_string()));
+            }
+            let key = S::read(&data[i..]);
+            let bits_len = data[i + S::SIZE] as usize;
+            let value_len = bits_len.div_ceil(8);
+            i += S::SIZE + 1;
+            if bits_len == 0 {
+                return Err(Error::InvalidHeader(format!("empty code for {}", key.name())));
+            }
+            if i + value_len > end {
+                return Err(Error::InvalidHeader("truncated table entry".to_string()));
+            }
+            let value = data[i..i + value_len].to_vec();
+            i += value_len;
+            if !bits_len.is_multiple_of(8) && value[value_len - 1] & (0xff >> (bits_len % 8)) != 0 {
+                return Err(Error::InvalidHeader(format!(
+                    "padding bits of the code for {} are not zero", key.name()
+                )));
+            }
+            if table.0.insert(key, BitSet { data: value, len: bits_len }).is_some() {
+                return Err(Error::InvalidHeader(format!("duplicate code for {}", key.name())));
+            }
+        }
+        table.validate()?;
+        Ok((table, end))
+    }
+}
+
+impl Table {
+    pub fn convert(&self, data: &[u8]) -> BitSet {
+        let mut bitset = BitSet::new();
+        for byte in data {
+            bitset.concat(&self.0[byte]);
+        }
+        bitset
+    }
+
+    /// Decode the first `bits_len` bits of `data`.
+    pub fn decode(&self, data: &[u8], bits_len: usize) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.decode_to(data, bits_len, &mut content)?;
+        Ok(content)
+    }
+
+    /// Decode the first `bits_len` bits of `data` into `out`.
+    pub fn decode_to<W: Write>(&self, data: &[u8], bits_len: usize, out: &mut W) -> Result<()> {
+        if bits_len > data.len() * 8 {
+            return Err(Error::UnexpectedEof);
+        }
+        self.decode_from(&mut BitReader::new(data), bits_len as u64, out)
+    }
+
+    /// Decode `bits_len` bits read from `reader` into `out`.
+    pub fn decode_from<R: Read, W: Write>(
+        &self, reader: &mut BitReader<R>, bits_len: u64, out: &mut W
+    ) -> Result<()> {
+        let trie = Trie::new(self)?;
+        let mut buffer = Vec::with_capacity(DECODE_BUFFER_SIZE);
+        let mut node = 0;
+        for i in 0..bits_len {
+            let bit = match reader.read_bit()? {
+                Some(bit) => bit,
+                None      => return Err(Error::UnexpectedEof),
+            };
+            node = match trie.0[node].children[bit as usize] {
+                Some(child) => child,
+                None => return Err(Error::InvalidPayload(format!("no code matches bit {}", i))),
+            };
+            if let Some(symbol) = trie.0[node].symbol {
+                buffer.push(symbol);
+                node = 0;
+                if buffer.len() == DECODE_BUFFER_SIZE {
+                    out.write_all(&buffer)?;
+                    buffer.clear();
+                }
+            }
+        }
+        if node != 0 {
+            return Err(Error::InvalidPayload("last code is truncated".to_string()));
+        }
+        out.write_all(&buffer)?;
+        Ok(())
+    }
+
+    /// Write the codes of `data` to `writer`.
+    pub fn encode_to<W: Write>(&self, data: &[u8], writer: &mut BitWriter<W>) -> Result<()> {
+        for byte in data {
+            match self.0.get(byte) {
+                Some(code) => writer.write_bitset(code)?,
+                None       => return Err(Error::InvalidTable(format!("no code for byte {}", byte))),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the Kraft inequality, which any prefix-free code satisfies:
+/// there are no more codes of each length than the shorter ones leave room for.
+pub fn check_kraft(lengths: impl Iterator<Item = usize>) -> Result<()> {
+    let mut counts = [0usize; 256];
+    for len in lengths {
+        counts[len.min(255)] += 1;
+    }
+    // Codes of the current length that are not a prefix of a shorter one,
+    // capped as there are at most 2^16 codes.
+    let mut available: usize = 1;
+    for count in counts.iter() {
+        if *count > available {
+            return Err(Error::InvalidTable("code lengths are oversubscribed".to_string()));
+        }
+        available = ((available - count) * 2).min(1 << 17);
+    }
+    Ok(())
+}
+
+struct TrieNode<S> {
+    children: [Option<usize>; 2],
+    symbol: Option<S>,
+}
+
+/// Decoding tree rebuilt from the codes of a table, of symbols of type `S`.
+pub struct Trie<S = u8>(Vec<TrieNode<S>>);
+
+impl<S: Symbol> Trie<S> {
+    pub fn new(table: &Table<S>) -> Result<Trie<S>> {
+        Trie::from_codes(&table.0)
+    }
+
+    pub fn from_codes(codes: &HashMap<S, BitSet>) -> Result<Trie<S>> {
+        let mut nodes = vec![TrieNode { children: [None, None], symbol: None }];
+        for (k, v) in codes {
+            let mut node = 0;
+            for i in 0..v.len {
+                if nodes[node].symbol.is_some() {
+                    return Err(Error::InvalidTable("codes are not prefix-free".to_string()));
+                }
+                let bit = v.get(i) as usize;
+                node = match nodes[node].children[bit] {
+                    Some(child) => child,
+                    None => {
+                        nodes.push(TrieNode { children: [None, None], symbol: None });
+                        nodes[node].children[bit] = Some(nodes.len() - 1);
+                        nodes.len() - 1
+                    },
+                };
+            }
+            if node == 0 || nodes[node].symbol.is_some() || nodes[node].children != [None, None] {
+                return Err(Error::InvalidTable("codes are not prefix-free".to_string()));
+            }
+            nodes[node].symbol = Some(*k);
+        }
+        Ok(Trie(nodes))
+    }
+
+    /// Read the code of one symbol from `reader`.
+    pub fn read_symbol<R: Read>(&self, reader: &mut BitReader<R>) -> Result<S> {
+        let mut node = 0;
+        loop {
+            let bit = reader.read_bit()?.ok_or(Error::UnexpectedEof)?;
+            node = self.0[node].children[bit as usize]
+                .ok_or_else(|| Error::InvalidPayload("no code matches the payload".to_string()))?;
+            if let Some(symbol) = self.0[node].symbol {
+                return Ok(symbol);
+            }
+        }
+    }
 }
 
 use std::fmt;
@@ -45,8 +314,74 @@ use std::fmt;
 impl fmt::Debug for Table {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for (k, v) in self.0.iter() {
-            write!(f, "{:4} {:?}\n", format!("{:?}", *k as char), v)?;
+            writeln!(f, "{:4} {:?}", format!("{:?}", *k as char), v)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(codes: &[(u8, &str)]) -> Table {
+        let mut table = Table::new();
+        for (symbol, code) in codes {
+            let mut bits = BitSet::new();
+            for c in code.chars() {
+                bits.push_bit(if c == '1' { 1 } else { 0 });
+            }
+            table.0.insert(*symbol, bits);
+        }
+        table
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let table = Table::from_tree(&Tree::from_data(b"abracadabra"));
+        let serialized = table.serialize();
+        let (deserialized, size) = Table::deserialize(&serialized).unwrap();
+        assert_eq!(size, serialized.len());
+        assert_eq!(deserialized.0, table.0);
+    }
+
+    #[test]
+    fn validate() {
+        assert!(table(&[(0, "0"), (1, "10"), (2, "11")]).validate().is_ok());
+        assert!(table(&[(0, "0"), (1, "10")]).validate().is_ok());
+        assert!(table(&[(0, "0"), (1, "01")]).validate().is_err());
+        assert!(table(&[(0, "")]).validate().is_err());
+    }
+
+    #[test]
+    fn too_long() {
+        let long = format!("1{}", "0".repeat(299));
+        let t = table(&[(0, "0"), (1, &long)]);
+        assert_eq!(
+            t.validate().unwrap_err().to_string(),
+            "invalid table: code of 300 bits for '\\u{1}' is longer than 255 bits"
+        );
+        let max = format!("1{}", "0".repeat(254));
+        assert!(table(&[(0, "0"), (1, &max)]).validate().is_ok());
+    }
+
+    #[test]
+    fn oversubscribed() {
+        let t = table(&[(0, "0"), (1, "1"), (2, "00")]);
+        assert_eq!(t.validate().unwrap_err().to_string(), "invalid table: code lengths are oversubscribed");
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        // not prefix-free
+        assert!(Table::<u8>::deserialize(&[0, 0, 0, 6, b'a', 1, 0x80, b'b', 2, 0x80]).is_err());
+        // non-zero padding bits
+        assert!(Table::<u8>::deserialize(&[0, 0, 0, 3, b'a', 1, 0x81]).is_err());
+        // size past the end of the data
+        assert!(Table::<u8>::deserialize(&[0, 0, 0, 9, b'a', 1, 0x80]).is_err());
+        // size larger than any table
+        assert!(Table::<u8>::deserialize(&[0xff, 0xff, 0xff, 0xff]).is_err());
+        // truncated entry
+        assert!(Table::<u8>::deserialize(&[0, 0, 0, 3, b'a', 9, 0x80]).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! DEFLATE (RFC 1951) encoder, producing raw streams readable by any inflate implementation.
+//!
+//! The data is split in LZ77 tokens (see `lz77`) with DEFLATE's window and match lengths,
+//! then in blocks of tokens, each written as whichever of a stored block, a block with
+//! the fixed codes or a block with its own codes is the smallest. The codes of a dynamic
+//! block are canonical codes of at most 15 bits, whose lengths are sent run-length encoded
+//! and themselves coded with a code of at most 7 bits.
+
+use super::bits::LsbWriter;
+use super::canonical;
+use super::lz77::{self, Token};
+
+/// Number of tokens in a block, each block having its own codes.
+const BLOCK_TOKENS: usize = 1 << 14;
+
+/// Size of the window, the largest distance DEFLATE can code.
+pub const WINDOW_SIZE: usize = 1 << 15;
+
+/// Largest stored block.
+const MAX_STORED: usize = 0xffff;
+
+const END_OF_BLOCK: usize = 256;
+
+/// Literal and length symbols, 286 and 287 being unused.
+const LITLEN_SYMBOLS: usize = 286;
+const DIST_SYMBOLS: usize = 30;
+
+const MAX_CODE_LEN: usize = 15;
+const MAX_CODE_LEN_CODE_LEN: usize = 7;
+
+/// Shortest length of each length symbol from 257, with its number of extra bits.
+pub const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+pub const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Shortest distance of each distance symbol, with its number of extra bits.
+pub const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// Order the code lengths of the code length symbols are sent in.
+pub const CODE_LEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Code lengths of the fixed literal and length code.
+pub fn fixed_litlen_lengths() -> Vec<u8> {
+    (0..288)
+        .map(|s| match s {
+            0..=143   => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _         => 8,
+        })
+        .collect()
+}
+
+/// Code lengths of the fixed distance code.
+pub fn fixed_dist_lengths() -> Vec<u8> {
+    vec![5; 32]
+}
+
+/// Length symbol of `length` with the value of its extra bits.
+fn length_symbol(length: usize) -> (usize, u32) {
+    let i = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+    (257 + i, (length - LENGTH_BASE[i] as usize) as u32)
+}
+
+/// Distance symbol of `distance` with the value of its extra bits.
+fn dist_symbol(distance: usize) -> (usize, u32) {
+    let i = DIST_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+    (i, (distance - DIST_BASE[i] as usize) as u32)
+}
+
+/// A code as the length and code of each symbol.
+struct Code {
+    lengths: Vec<u8>,
+    codes: Vec<u32>,
+}
+
+impl Code {
+    fn new(lengths: Vec<u8>) -> Code {
+        let codes = canonical::codes(&lengths);
+        Code { lengths, codes }
+    }
+
+    /// Length limited code built from the counts of the symbols.
+    fn from_counts(counts: &[usize], max_len: usize) -> Code {
+        Code::new(canonical::lengths(counts, max_len))
+    }
+
+    fn write(&self, writer: &mut LsbWriter, symbol: usize) {
+        writer.write_code(self.codes[symbol], self.lengths[symbol] as u32);
+    }
+
+    /// Bits taken by the symbols counted in `counts`.
+    fn cost(&self, counts: &[usize]) -> u64 {
+        counts.iter().zip(&self.lengths).map(|(c, len)| *c as u64 * *len as u64).sum()
+    }
+}
+
+/// Give a count to the first symbols until at least two have one, as some inflaters
+/// reject codes of a single symbol.
+fn at_least_two(counts: &mut [usize]) {
+    let mut missing = 2usize.saturating_sub(counts.iter().filter(|c| **c > 0).count());
+    for count in counts.iter_mut() {
+        if missing == 0 {
+            break;
+        }
+        if *count == 0 {
+            *count = 1;
+            missing -= 1;
+        }
+    }
+}
+
+/// Run-length encode code lengths as code length symbols with the value of their extra bits:
+/// 16 repeats the previous length 3 to 6 times, 17 and 18 repeat zero 3 to 10 and 11 to 138 times.
+fn code_len_symbols(lengths: &[u8]) -> Vec<(u8, u32)> {
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|l| **l == len).count();
+        if len == 0 && run >= 3 {
+            let run = run.min(138);
+            symbols.push(if run <= 10 { (17, run as u32 - 3) } else { (18, run as u32 - 11) });
+            i += run;
+        } else if len != 0 && run >= 4 {
+            symbols.push((len, 0));
+            let run = (run - 1).min(6);
+            symbols.push((16, run as u32 - 3));
+            i += 1 + run;
+        } else {
+            symbols.push((len, 0));
+            i += 1;
+        }
+    }
+    symbols
+}
+
+fn code_len_extra(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _  => 0,
+    }
+}
+
+/// Header of a dynamic block, given the codes of its literals and lengths and of its distances.
+struct DynamicHeader {
+    hlit: usize,
+    hdist: usize,
+    symbols: Vec<(u8, u32)>,
+    code: Code,
+    hclen: usize,
+}
+
+impl DynamicHeader {
+    fn new(litlen: &Code, dist: &Code) -> DynamicHeader {
+        let hlit = 257.max(litlen.lengths.iter().rposition(|l| *l > 0).map_or(0, |i| i + 1));
+        let hdist = 1.max(dist.lengths.iter().rposition(|l| *l > 0).map_or(0, |i| i + 1));
+        let mut lengths = litlen.lengths[..hlit].to_vec();
+        lengths.extend_from_slice(&dist.lengths[..hdist]);
+        let symbols = code_len_symbols(&lengths);
+
+        let mut counts = [0; 19];
+        for (symbol, _) in &symbols {
+            counts[*symbol as usize] += 1;
+        }
+        let code = Code::from_counts(&counts, MAX_CODE_LEN_CODE_LEN);
+        let hclen = 4.max(CODE_LEN_ORDER.iter().rposition(|s| code.lengths[*s] > 0).map_or(0, |i| i + 1));
+        DynamicHeader { hlit, hdist, symbols, code, hclen }
+    }
+
+    fn cost(&self) -> u64 {
+        let symbols: u64 = self.symbols
+            .iter()
+            .map(|(s, _)| self.code.lengths[*s as usize] as u64 + code_len_extra(*s) as u64)
+            .sum();
+        5 + 5 + 4 + 3 * self.hclen as u64 + symbols
+    }
+
+    fn write(&self, writer: &mut LsbWriter) {
+        writer.write_bits((self.hlit - 257) as u32, 5);
+        writer.write_bits((self.hdist - 1) as u32, 5);
+        writer.write_bits((self.hclen - 4) as u32, 4);
+        for symbol in &CODE_LEN_ORDER[..self.hclen] {
+            writer.write_bits(self.code.lengths[*symbol] as u32, 3);
+        }
+        for (symbol, extra) in &self.symbols {
+            self.code.write(writer, *symbol as usize);
+            writer.write_bits(*extra, code_len_extra(*symbol));
+        }
+    }
+}
+
+/// Bits taken by the extra bits of the lengths and distances counted.
+fn extra_cost(litlen: &[usize], dist: &[usize]) -> u64 {
+    let lengths: u64 = LENGTH_EXTRA.iter().zip(&litlen[257..]).map(|(e, c)| *e as u64 * *c as u64).sum();
+    let distances: u64 = DIST_EXTRA.iter().zip(dist).map(|(e, c)| *e as u64 * *c as u64).sum();
+    lengths + distances
+}
+
+fn write_tokens(writer: &mut LsbWriter, tokens: &[Token], litlen: &Code, dist: &Code) {
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => litlen.write(writer, b as usize),
+            Token::Match { length, distance } => {
+                let (symbol, extra) = length_symbol(length);
+                litlen.write(writer, symbol);
+                writer.write_bits(extra, LENGTH_EXTRA[symbol - 257] as u32);
+                let (symbol, extra) = dist_symbol(distance);
+                dist.write(writer, symbol);
+                writer.write_bits(extra, DIST_EXTRA[symbol] as u32);
+            },
+        }
+    }
+    litlen.write(writer, END_OF_BLOCK);
+}
+
+/// Write `tokens`, which decode to `data`, as the smallest kind of block.
+fn write_block(writer: &mut LsbWriter, tokens: &[Token], data: &[u8], last: bool) {
+    let mut litlen_counts = vec![0; LITLEN_SYMBOLS];
+    let mut dist_counts = vec![0; DIST_SYMBOLS];
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => litlen_counts[b as usize] += 1,
+            Token::Match { length, distance } => {
+                litlen_counts[length_symbol(length).0] += 1;
+                dist_counts[dist_symbol(distance).0] += 1;
+            },
+        }
+    }
+    litlen_counts[END_OF_BLOCK] += 1;
+    let extra = extra_cost(&litlen_counts, &dist_counts);
+
+    let fixed = (Code::new(fixed_litlen_lengths()), Code::new(fixed_dist_lengths()));
+    let fixed_cost = 3 + fixed.0.cost(&litlen_counts) + fixed.1.cost(&dist_counts) + extra;
+
+    let mut dynamic_counts = (litlen_counts.clone(), dist_counts.clone());
+    at_least_two(&mut dynamic_counts.0);
+    at_least_two(&mut dynamic_counts.1);
+    let dynamic = (
+        Code::from_counts(&dynamic_counts.0, MAX_CODE_LEN),
+        Code::from_counts(&dynamic_counts.1, MAX_CODE_LEN),
+    );
+    let header = DynamicHeader::new(&dynamic.0, &dynamic.1);
+    let dynamic_cost = 3 + header.cost() + dynamic.0.cost(&litlen_counts) + dynamic.1.cost(&dist_counts) + extra;
+
+    // each stored block is padded to a byte boundary, counted as the largest padding
+    let stored_cost = data.len().div_ceil(MAX_STORED).max(1) as u64 * (3 + 7 + 32) + data.len() as u64 * 8;
+
+    if stored_cost < fixed_cost.min(dynamic_cost) {
+        let mut chunks = data.chunks(MAX_STORED).peekable();
+        if chunks.peek().is_none() {
+            write_stored(writer, &[], last);
+        }
+        while let Some(chunk) = chunks.next() {
+            write_stored(writer, chunk, last && chunks.peek().is_none());
+        }
+    } else if fixed_cost <= dynamic_cost {
+        writer.write_bits(last as u32, 1);
+        writer.write_bits(1, 2);
+        write_tokens(writer, tokens, &fixed.0, &fixed.1);
+    } else {
+        writer.write_bits(last as u32, 1);
+        writer.write_bits(2, 2);
+        header.write(writer);
+        write_tokens(writer, tokens, &dynamic.0, &dynamic.1);
+    }
+}
+
+fn write_stored(writer: &mut LsbWriter, data: &[u8], last: bool) {
+    writer.write_bits(last as u32, 1);
+    writer.write_bits(0, 2);
+    writer.align();
+    writer.write_bits(data.len() as u32, 16);
+    writer.write_bits(!data.len() as u32, 16);
+    writer.write_bytes(data);
+}
+
+/// Compress `data` to a raw DEFLATE stream.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let tokens = lz77::tokens(data, WINDOW_SIZE);
+    let mut writer = LsbWriter::new();
+    let mut start = 0;
+    let mut blocks = tokens.chunks(BLOCK_TOKENS).peekable();
+    if blocks.peek().is_none() {
+        write_block(&mut writer, &[], &[], true);
+    }
+    while let Some(block) = blocks.next() {
+        let size: usize = block
+            .iter()
+            .map(|t| match t {
+                Token::Literal(_)           => 1,
+                Token::Match { length, .. } => *length,
+            })
+            .sum();
+        write_block(&mut writer, block, &data[start..start + size], blocks.peek().is_none());
+        start += size;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bench;
+
+    #[test]
+    fn symbols() {
+        assert_eq!(length_symbol(3), (257, 0));
+        assert_eq!(length_symbol(12), (265, 1));
+        assert_eq!(length_symbol(257), (284, 30));
+        assert_eq!(length_symbol(258), (285, 0));
+        assert_eq!(dist_symbol(1), (0, 0));
+        assert_eq!(dist_symbol(6), (4, 1));
+        assert_eq!(dist_symbol(32768), (29, 8191));
+    }
+
+    #[test]
+    fn code_lengths_run_length() {
+        assert_eq!(code_len_symbols(&[0, 0, 0, 5, 5, 5, 5, 5, 2, 0, 0]), vec![
+            (17, 0), (5, 0), (16, 1), (2, 0), (0, 0), (0, 0),
+        ]);
+        assert_eq!(code_len_symbols(&[0; 150]), vec![(18, 127), (18, 1)]);
+        assert_eq!(code_len_symbols(&[8; 8]), vec![(8, 0), (16, 3), (8, 0)]);
+    }
+
+    #[test]
+    fn fixed_blocks() {
+        // as zlib.compressobj(wbits=-15) outputs them
+        assert_eq!(deflate(b""), vec![0x03, 0x00]);
+        assert_eq!(deflate(b"a"), vec![0x4b, 0x04, 0x00]);
+        // a, b, c then a match of 9 bytes 3 bytes back, zlib finding a shorter one first
+        assert_eq!(deflate(b"abcabcabcabc"), vec![0x4b, 0x4c, 0x4a, 0x86, 0x23, 0x00]);
+    }
+
+    #[test]
+    fn block_kinds() {
+        // random data is stored, each block of tokens having a 5 bytes header
+        let random = bench::random(70000);
+        let out = deflate(&random);
+        assert_eq!(out[0] & 0b111, 0b000);
+        assert_eq!(out.len(), random.len() + random.len().div_ceil(BLOCK_TOKENS) * 5);
+        assert_eq!(&out[5..10], &random[..5]);
+
+        // skewed data gets its own code
+        let text: Vec<u8> = (0..5000u32).map(|i| b"etaoin shrdlu"[(i * i % 13) as usize]).collect();
+        assert_eq!(deflate(&text)[0] & 0b111, 0b101);
+    }
+}
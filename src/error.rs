@@ -0,0 +1,48 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidMagic,
+    UnexpectedEof,
+    InvalidHeader(String),
+    InvalidPayload(String),
+    InvalidTable(String),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    Parse(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e)             => write!(f, "{}", e),
+            Error::InvalidMagic      => write!(f, "not in huffman format"),
+            Error::UnexpectedEof     => write!(f, "unexpected end of file"),
+            Error::InvalidHeader(s)  => write!(f, "invalid header: {}", s),
+            Error::InvalidPayload(s) => write!(f, "invalid payload: {}", s),
+            Error::InvalidTable(s)   => write!(f, "invalid table: {}", s),
+            Error::ChecksumMismatch { expected, actual } => write!(
+                f, "checksum mismatch: expected {:08x}, got {:08x}", expected, actual
+            ),
+            Error::Parse(s)          => write!(f, "parse error: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _            => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
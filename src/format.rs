@@ -0,0 +1,43 @@
+//! Detection of the format of compressed data by its first bytes.
+
+use super::{container, gzip, zlib};
+
+/// Bytes needed to detect the format of some data.
+pub const PREFIX_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Huffman,
+    Gzip,
+    Zlib,
+}
+
+impl Format {
+    /// Format of data starting with `prefix`, `None` if it is not one of them.
+    pub fn detect(prefix: &[u8]) -> Option<Format> {
+        if prefix.starts_with(container::MAGIC) {
+            Some(Format::Huffman)
+        } else if prefix.starts_with(&gzip::MAGIC) {
+            Some(Format::Gzip)
+        } else if zlib::is_zlib(prefix) {
+            Some(Format::Zlib)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::metadata::Metadata;
+
+    #[test]
+    fn detect() {
+        assert_eq!(Format::detect(&container::compress(b"abc")), Some(Format::Huffman));
+        assert_eq!(Format::detect(&gzip::compress(b"abc", &Metadata::default())), Some(Format::Gzip));
+        assert_eq!(Format::detect(&zlib::compress(b"abc")), Some(Format::Zlib));
+        assert_eq!(Format::detect(b"abc"), None);
+        assert_eq!(Format::detect(b""), None);
+    }
+}
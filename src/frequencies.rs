@@ -0,0 +1,150 @@
+//! Counts of the bytes scaled to frequencies adding up to a power of two, as coded by `range` and `tans`,
+//! and the header they are stored in:
+//!
+//! ```text
+//! 8 byte unsigned int: size of the data
+//! 2 byte unsigned int: number of bytes that appear in the data
+//! for each of them, in increasing order:
+//!     1 byte: the byte
+//!     2 byte unsigned int: its frequency
+//! ```
+
+use super::error::{Error, Result};
+use super::tree;
+
+/// Frequency of each byte out of `total`, 0 for bytes not in `data`.
+///
+/// No frequency is `total` so that every byte takes some room in the output:
+/// data made of a single byte also gives a frequency of 1 to the next byte.
+pub fn normalize(data: &[u8], total: u32) -> [u32; 256] {
+    let mut freqs = [0; 256];
+    let counter = tree::count(data);
+    let count: usize = counter.values().sum();
+    for (byte, c) in &counter {
+        freqs[*byte as usize] = ((*c as u64 * total as u64 / count as u64) as u32).max(1);
+    }
+    if counter.len() == 1 {
+        let byte = *counter.keys().next().unwrap();
+        freqs[byte as usize] = total - 1;
+        freqs[byte.wrapping_add(1) as usize] = 1;
+        return freqs;
+    }
+    // rounding may give more or less than `total` in all, which the largest frequency makes up for
+    let mut sum: u32 = freqs.iter().sum();
+    while sum > total {
+        let largest = (0..256).max_by_key(|b| freqs[*b]).unwrap();
+        freqs[largest] -= 1;
+        sum -= 1;
+    }
+    if sum > 0 && sum < total {
+        let largest = (0..256).max_by_key(|b| freqs[*b]).unwrap();
+        freqs[largest] += total - sum;
+    }
+    freqs
+}
+
+/// Header of `size` bytes of data of frequencies `freqs`, which all fit in 16 bits.
+pub fn header(size: u64, freqs: &[u32; 256]) -> Vec<u8> {
+    let mut out = size.to_be_bytes().to_vec();
+    let present: Vec<usize> = (0..256).filter(|b| freqs[*b] > 0).collect();
+    out.extend_from_slice(&(present.len() as u16).to_be_bytes());
+    for b in present {
+        out.push(b as u8);
+        out.extend_from_slice(&(freqs[b] as u16).to_be_bytes());
+    }
+    out
+}
+
+/// Read a header whose frequencies add up to at most `total`, each of them below it,
+/// returns the size of the data, the frequencies and what follows the header.
+pub fn read_header(data: &[u8], total: u32) -> Result<(u64, [u32; 256], &[u8])> {
+    if data.len() < 10 {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut size = [0; 8];
+    size.copy_from_slice(&data[..8]);
+    let size = u64::from_be_bytes(size);
+    let count = u16::from_be_bytes([data[8], data[9]]) as usize;
+    let entries = data.get(10..10 + 3 * count).ok_or(Error::UnexpectedEof)?;
+    let mut freqs = [0; 256];
+    let mut previous = None;
+    for entry in entries.chunks_exact(3) {
+        if previous.is_some_and(|p| p >= entry[0]) {
+            return Err(Error::InvalidTable("bytes are not in increasing order".to_string()));
+        }
+        previous = Some(entry[0]);
+        freqs[entry[0] as usize] = u16::from_be_bytes([entry[1], entry[2]]) as u32;
+    }
+    if freqs.iter().filter(|f| **f > 0).count() != count {
+        return Err(Error::InvalidTable("byte of frequency 0".to_string()));
+    }
+    if freqs.iter().sum::<u32>() > total {
+        return Err(Error::InvalidTable(format!("frequencies add up to more than {}", total)));
+    }
+    if freqs.contains(&total) {
+        return Err(Error::InvalidTable("a single byte has the whole frequency".to_string()));
+    }
+    if size > 0 && count == 0 {
+        return Err(Error::InvalidTable("no frequencies for non-empty data".to_string()));
+    }
+    Ok((size, freqs, &data[10 + 3 * count..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_up() {
+        for total in [1 << 12, 1 << 16] {
+            let freqs = normalize(b"abracadabra", total);
+            assert_eq!(freqs.iter().sum::<u32>(), total);
+            assert!(freqs[b'a' as usize] > freqs[b'b' as usize] && freqs[b'b' as usize] > freqs[b'c' as usize]);
+            assert_eq!(freqs[b'z' as usize], 0);
+            let single = normalize(&[0xff; 1000], total);
+            assert_eq!((single[0xff], single[0]), (total - 1, 1));
+            assert_eq!(normalize(b"", total).iter().sum::<u32>(), 0);
+            let all: Vec<u8> = (0..=255).chain(std::iter::repeat_n(0, 1 << 20)).collect();
+            let freqs = normalize(&all, total);
+            assert!(freqs.iter().all(|f| *f > 0));
+            assert_eq!(freqs.iter().sum::<u32>(), total);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let freqs = normalize(b"abracadabra", 1 << 12);
+        let mut data = header(11, &freqs);
+        assert_eq!(data.len(), 10 + 3 * 5);
+        data.push(42);
+        let (size, read, rest) = read_header(&data, 1 << 12).unwrap();
+        assert_eq!((size, read, rest), (11, freqs, &[42][..]));
+        let empty = header(0, &[0; 256]);
+        assert_eq!(read_header(&empty, 1 << 12).unwrap(), (0, [0; 256], &[][..]));
+    }
+
+    #[test]
+    fn errors() {
+        let data = header(11, &normalize(b"abracadabra", 1 << 12));
+        assert!(matches!(read_header(&data[..data.len() - 1], 1 << 12), Err(Error::UnexpectedEof)));
+        assert!(matches!(read_header(&[], 1 << 12), Err(Error::UnexpectedEof)));
+        // 'b' before 'a'
+        let mut order = data.clone();
+        order.swap(10, 13);
+        assert!(matches!(read_header(&order, 1 << 12), Err(Error::InvalidTable(_))));
+        let mut zero = data.clone();
+        zero[14] = 0;
+        zero[15] = 0;
+        assert!(matches!(read_header(&zero, 1 << 12), Err(Error::InvalidTable(_))));
+        // frequencies adding up to more than 2^12
+        let mut freqs = data.clone();
+        freqs[12] += 1;
+        assert!(matches!(read_header(&freqs, 1 << 12), Err(Error::InvalidTable(_))));
+        let mut whole = 1u64.to_be_bytes().to_vec();
+        whole.extend_from_slice(&[0, 1, b'a', 0x10, 0x00]);
+        assert!(matches!(read_header(&whole, 1 << 12), Err(Error::InvalidTable(_))));
+        let mut no_freqs = 1u64.to_be_bytes().to_vec();
+        no_freqs.extend_from_slice(&[0, 0]);
+        assert!(matches!(read_header(&no_freqs, 1 << 12), Err(Error::InvalidTable(_))));
+    }
+}
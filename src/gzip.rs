@@ -0,0 +1,233 @@
+//! gzip files (RFC 1952): members made of a header, a DEFLATE stream and the CRC-32 and size of the data.
+//!
+//! ```text
+//! 2 bytes magic: 1f 8b
+//! 1 byte compression method: 8 (DEFLATE)
+//! 1 byte flags: which of the following optional fields are present
+//!     bit 1: FHCRC, bit 2: FEXTRA, bit 3: FNAME, bit 4: FCOMMENT (bit 0, FTEXT, is a hint)
+//! 4 byte unsigned int: modification time in seconds since the Unix epoch, 0 if unknown
+//! 1 byte extra flags, 1 byte operating system
+//! FEXTRA: 2 byte unsigned int size and extra field
+//! FNAME: zero-terminated ISO 8859-1 file name
+//! FCOMMENT: zero-terminated comment
+//! FHCRC: 2 lowest bytes of the CRC-32 of the header
+//! DEFLATE stream
+//! 4 byte unsigned int: CRC-32 of the data
+//! 4 byte unsigned int: size of the data modulo 2^32
+//! ```
+//!
+//! Integers are little endian. A file can be made of several members, its data being theirs concatenated.
+
+use std::convert::TryFrom;
+
+use super::checksum::crc32;
+use super::deflate::deflate;
+use super::error::{Error, Result};
+use super::inflate::inflate_prefix;
+use super::metadata::Metadata;
+
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+pub const SUFFIX: &str = ".gz";
+
+const METHOD_DEFLATE: u8 = 8;
+
+const FLAG_HCRC: u8    = 1 << 1;
+const FLAG_EXTRA: u8   = 1 << 2;
+const FLAG_NAME: u8    = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const FLAGS_RESERVED: u8 = 0xe0;
+
+/// Operating system field for an unknown one.
+const OS_UNKNOWN: u8 = 255;
+
+/// Compress `data` to a gzip member, storing the name and modification time of `metadata`.
+/// The name is only stored if it is made of ISO 8859-1 characters.
+pub fn compress(data: &[u8], metadata: &Metadata) -> Vec<u8> {
+    let name: Option<Vec<u8>> = metadata.name.as_ref().and_then(|name| {
+        name.chars().map(|c| u8::try_from(c as u32).ok().filter(|b| *b != 0)).collect()
+    });
+    let mtime = metadata.mtime.filter(|t| (1..=u32::MAX as i64).contains(t)).unwrap_or(0) as u32;
+
+    let mut out = MAGIC.to_vec();
+    out.push(METHOD_DEFLATE);
+    out.push(if name.is_some() { FLAG_NAME } else { 0 });
+    out.extend_from_slice(&mtime.to_le_bytes());
+    out.extend_from_slice(&[0, OS_UNKNOWN]);
+    if let Some(name) = name {
+        out.extend(name);
+        out.push(0);
+    }
+    out.extend(deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Zero-terminated field at the start of `data`, returns it without its terminator.
+fn zero_terminated(data: &[u8]) -> Result<&[u8]> {
+    let end = data.iter().position(|b| *b == 0).ok_or(Error::UnexpectedEof)?;
+    Ok(&data[..end])
+}
+
+/// Parse the header of a member at the start of `data`, returns the name and modification
+/// time it stores with its size.
+fn read_header(data: &[u8]) -> Result<(Metadata, usize)> {
+    if data.len() < 10 {
+        return Err(Error::UnexpectedEof);
+    }
+    if data[..2] != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    if data[2] != METHOD_DEFLATE {
+        return Err(Error::InvalidHeader(format!("unknown compression method {}", data[2])));
+    }
+    let flags = data[3];
+    if flags & FLAGS_RESERVED != 0 {
+        return Err(Error::InvalidHeader(format!("reserved flags {:#04x} are set", flags & FLAGS_RESERVED)));
+    }
+    let mut metadata = Metadata::default();
+    let mtime = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    if mtime != 0 {
+        metadata.mtime = Some(mtime as i64);
+    }
+    let mut pos = 10;
+    if flags & FLAG_EXTRA != 0 {
+        let size = data.get(pos..pos + 2).ok_or(Error::UnexpectedEof)?;
+        pos += 2 + u16::from_le_bytes([size[0], size[1]]) as usize;
+    }
+    if flags & FLAG_NAME != 0 {
+        let name = zero_terminated(data.get(pos..).ok_or(Error::UnexpectedEof)?)?;
+        metadata.name = Some(name.iter().map(|b| *b as char).collect());
+        pos += name.len() + 1;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        pos += zero_terminated(data.get(pos..).ok_or(Error::UnexpectedEof)?)?.len() + 1;
+    }
+    if flags & FLAG_HCRC != 0 {
+        let crc = data.get(pos..pos + 2).ok_or(Error::UnexpectedEof)?;
+        let expected = u16::from_le_bytes([crc[0], crc[1]]) as u32;
+        let actual = crc32(&data[..pos]) & 0xffff;
+        if expected != actual {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        }
+        pos += 2;
+    }
+    if pos > data.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok((metadata, pos))
+}
+
+/// Decompress all the members of `data`, returns their data with the name
+/// and modification time stored in the first one.
+pub fn decompress(data: &[u8]) -> Result<(Vec<u8>, Metadata)> {
+    let mut out = Vec::new();
+    let mut metadata = None;
+    let mut rest = data;
+    // zero bytes after the last member, left by tools padding to a block size, are ignored as gzip does
+    while metadata.is_none() || rest.iter().any(|b| *b != 0) {
+        let (member_metadata, header_size) = read_header(rest).map_err(|e| match (e, metadata.is_some()) {
+            (Error::InvalidMagic, true) => Error::InvalidPayload("trailing bytes after gzip member".to_string()),
+            (e, _)                      => e,
+        })?;
+        metadata.get_or_insert(member_metadata);
+        let (member, size) = inflate_prefix(&rest[header_size..])?;
+        let trailer = rest.get(header_size + size..header_size + size + 8).ok_or(Error::UnexpectedEof)?;
+        let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let actual = crc32(&member);
+        if expected != actual {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        }
+        let expected_size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if expected_size != member.len() as u32 {
+            return Err(Error::InvalidPayload(format!(
+                "member size is {} instead of {}", member.len() as u32, expected_size
+            )));
+        }
+        out.extend(member);
+        rest = &rest[header_size + size + 8..];
+    }
+    Ok((out, metadata.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // gzip.GzipFile(filename="hello.txt", mtime=1700000000) with b"hello hello hello\n"
+    const PYTHON_MEMBER: [u8; 39] = [
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0xf1, 0x53, 0x65, 0x02, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2e,
+        0x74, 0x78, 0x74, 0x00, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00, 0x3b,
+        0x7c, 0x8a, 0xdf, 0x12, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn python_member() {
+        let (data, metadata) = decompress(&PYTHON_MEMBER).unwrap();
+        assert_eq!(data, b"hello hello hello\n");
+        assert_eq!(metadata.name.as_deref(), Some("hello.txt"));
+        assert_eq!(metadata.mtime, Some(1700000000));
+    }
+
+    #[test]
+    fn round_trip() {
+        let metadata = Metadata { name: Some("café.txt".to_string()), mtime: Some(1234), ..Metadata::default() };
+        for data in [&b""[..], b"a", b"hello hello hello", &[0; 10_000]] {
+            assert_eq!(decompress(&compress(data, &metadata)).unwrap(), (data.to_vec(), metadata.clone()));
+        }
+        let (_, stored) = decompress(&compress(b"", &Metadata { name: Some("名".to_string()), ..Metadata::default() })).unwrap();
+        assert_eq!(stored, Metadata::default());
+    }
+
+    #[test]
+    fn members() {
+        let mut file = compress(b"hello ", &Metadata::default());
+        file.extend(PYTHON_MEMBER);
+        let (data, metadata) = decompress(&file).unwrap();
+        assert_eq!(data, b"hello hello hello hello\n");
+        assert_eq!(metadata, Metadata::default());
+    }
+
+    #[test]
+    fn optional_fields() {
+        let mut member = PYTHON_MEMBER[..10].to_vec();
+        member[3] = FLAG_EXTRA | FLAG_COMMENT | FLAG_HCRC;
+        member.extend_from_slice(&[3, 0, b'a', b'b', b'c']);
+        member.extend_from_slice(b"a comment\0");
+        member.extend_from_slice(&(crc32(&member) as u16).to_le_bytes());
+        member.extend_from_slice(&PYTHON_MEMBER[20..]);
+        assert_eq!(decompress(&member).unwrap().0, b"hello hello hello\n");
+
+        let header_crc = member.len() - 33;
+        member[header_crc] ^= 1;
+        assert!(matches!(decompress(&member), Err(Error::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn zero_padding() {
+        let mut padded = PYTHON_MEMBER.to_vec();
+        padded.extend_from_slice(&[0; 512]);
+        assert_eq!(decompress(&padded).unwrap().0, decompress(&PYTHON_MEMBER).unwrap().0);
+        // not a member made of zeroes either
+        assert!(matches!(decompress(&[0; 16]), Err(Error::InvalidMagic)));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(decompress(&PYTHON_MEMBER[..38]), Err(Error::UnexpectedEof)));
+        assert!(matches!(decompress(b""), Err(Error::UnexpectedEof)));
+        assert!(matches!(decompress(b"HUFF0000000"), Err(Error::InvalidMagic)));
+        let mut trailing = PYTHON_MEMBER.to_vec();
+        trailing.extend_from_slice(b"\0\0not a gzip member");
+        assert!(matches!(decompress(&trailing), Err(Error::InvalidPayload(_))));
+        let mut crc = PYTHON_MEMBER;
+        crc[31] ^= 1;
+        assert!(matches!(decompress(&crc), Err(Error::ChecksumMismatch { .. })));
+        let mut size = PYTHON_MEMBER;
+        size[35] ^= 1;
+        assert!(decompress(&size).is_err());
+        let mut reserved = PYTHON_MEMBER;
+        reserved[3] |= 0x80;
+        assert!(decompress(&reserved).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! HPACK (RFC 7541) string literals: the static Huffman code of HTTP/2 header compression.
+//!
+//! The code (RFC 7541 Appendix B) is canonical: codes are assigned in the order of their length,
+//! then of their symbol, from the following lengths of the 256 bytes and of EOS.
+//! Codes are written most significant bit first and the last byte is padded with the first bits
+//! of the code of EOS, which are all 1. A decoder must reject padding longer than 7 bits,
+//! padding with a 0 bit and the EOS symbol itself (RFC 7541 5.2).
+
+use super::bits::BitSet;
+use super::canonical::{self, Decoder};
+use super::conversion::Table;
+use super::error::{Error, Result};
+
+/// End of string symbol, never coded in a string.
+pub const EOS: u16 = 256;
+
+/// Code length of each byte and of EOS.
+const LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28,
+    28, 28, 28, 28, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6,
+    5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10,
+    13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6,
+    15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6, 6, 5,
+    6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28,
+    20, 22, 20, 20, 22, 22, 22, 23, 22, 23, 23, 23, 23, 23, 24, 23,
+    24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24,
+    22, 21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23,
+    21, 21, 22, 21, 23, 22, 23, 23, 20, 22, 22, 22, 23, 22, 22, 23,
+    26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25,
+    19, 21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27,
+    20, 24, 20, 21, 22, 21, 21, 23, 22, 22, 25, 25, 24, 24, 26, 23,
+    26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26,
+    30,
+];
+
+/// Code of each byte, EOS having no place in a `Table`.
+pub fn table() -> Table {
+    let mut table = Table::new();
+    for (symbol, code) in canonical::codes(&LENGTHS).iter().enumerate().take(256) {
+        let mut bits = BitSet::new();
+        for i in (0..LENGTHS[symbol]).rev() {
+            bits.push_bit((code >> i) as u8 & 1);
+        }
+        table.0.insert(symbol as u8, bits);
+    }
+    table
+}
+
+/// Size of `data` once encoded, padding included, to choose between a coded and a raw string literal.
+pub fn encoded_len(data: &[u8]) -> usize {
+    data.iter().map(|byte| LENGTHS[*byte as usize] as usize).sum::<usize>().div_ceil(8)
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let codes = canonical::codes(&LENGTHS);
+    let mut out = Vec::with_capacity(encoded_len(data));
+    // bits not written yet, in the low `len` bits
+    let (mut buffer, mut len) = (0u64, 0);
+    for byte in data {
+        let code_len = LENGTHS[*byte as usize] as u32;
+        buffer = buffer << code_len | codes[*byte as usize] as u64;
+        len += code_len;
+        while len >= 8 {
+            len -= 8;
+            out.push((buffer >> len) as u8);
+        }
+    }
+    if len > 0 {
+        out.push((buffer << (8 - len)) as u8 | 0xff >> len);
+    }
+    out
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let decoder = Decoder::new(&LENGTHS)?;
+    let bit = |i: usize| data[i / 8] >> (7 - i % 8) & 1;
+    let len = data.len() * 8;
+    let mut out = Vec::with_capacity(data.len() * 8 / 5);
+    let mut pos = 0;
+    while pos < len {
+        // no code shorter than EOS is made of 1 bits only, so these are the padding
+        if len - pos < 8 && (pos..len).all(|i| bit(i) == 1) {
+            break;
+        }
+        let symbol = decoder.decode(|| {
+            if pos == len {
+                return Err(Error::InvalidPayload(
+                    "padding longer than 7 bits or not made of the first bits of EOS".to_string()
+                ));
+            }
+            pos += 1;
+            Ok(bit(pos - 1))
+        })?;
+        if symbol == EOS {
+            return Err(Error::InvalidPayload("EOS in a string literal".to_string()));
+        }
+        out.push(symbol as u8);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code() {
+        let table = table();
+        assert_eq!(table.0.len(), 256);
+        // RFC 7541 Appendix B
+        assert_eq!(table.0[&b'0'].to_string(), "00000");
+        assert_eq!(table.0[&b'a'].to_string(), "00011");
+        assert_eq!(table.0[&255].to_string(), "11111111111111111111101110");
+        assert_eq!(canonical::codes(&LENGTHS)[EOS as usize], 0x3fffffff);
+        table.validate().unwrap();
+    }
+
+    #[test]
+    fn rfc_examples() {
+        // RFC 7541 C.4 and C.6
+        let examples: [(&[u8], &[u8]); 8] = [
+            (b"www.example.com", &[0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff]),
+            (b"no-cache", &[0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf]),
+            (b"custom-key", &[0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xa9, 0x7d, 0x7f]),
+            (b"custom-value", &[0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf]),
+            (b"302", &[0x64, 0x02]),
+            (b"private", &[0xae, 0xc3, 0x77, 0x1a, 0x4b]),
+            (b"Mon, 21 Oct 2013 20:13:21 GMT", &[
+                0xd0, 0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95,
+                0x04, 0x0b, 0x81, 0x66, 0xe0, 0x82, 0xa6, 0x2d, 0x1b, 0xff,
+            ]),
+            (b"https://www.example.com", &[
+                0x9d, 0x29, 0xad, 0x17, 0x18, 0x63, 0xc7, 0x8f, 0x0b, 0x97, 0xc8, 0xe9, 0xae, 0x82, 0xae, 0x43, 0xd3,
+            ]),
+        ];
+        for (data, encoded) in examples {
+            assert_eq!(encode(data), encoded);
+            assert_eq!(encoded_len(data), encoded.len());
+            assert_eq!(decode(encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..=255).chain(0..=255).collect();
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+        assert_eq!(encode(b""), b"");
+        assert_eq!(decode(b"").unwrap(), b"");
+        assert_eq!(table().convert(&data).len, encode(&data).len() * 8 - 4);
+    }
+
+    #[test]
+    fn padding() {
+        // "302" followed by 8 bits of padding
+        assert!(decode(&[0x64, 0x02, 0xff]).is_err());
+        // padding with a 0 bit: '0' is 00000, then 011 is not padding
+        assert!(decode(&[0x03]).is_err());
+        assert_eq!(decode(&[0x07]).unwrap(), b"0");
+        // EOS followed by its padding
+        assert!(decode(&[0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode(&[0x1f, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+}
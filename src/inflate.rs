@@ -0,0 +1,171 @@
+//! DEFLATE (RFC 1951) decoder, for the streams of `deflate` and of any other encoder.
+
+use super::bits::LsbReader;
+use super::canonical::Decoder;
+use super::deflate::{self, CODE_LEN_ORDER, DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+use super::error::{Error, Result};
+
+const END_OF_BLOCK: u16 = 256;
+
+fn read_bits(reader: &mut LsbReader, len: u32) -> Result<u32> {
+    reader.read_bits(len).ok_or(Error::UnexpectedEof)
+}
+
+fn decode(decoder: &Decoder, reader: &mut LsbReader) -> Result<u16> {
+    decoder.decode(|| reader.read_bit().ok_or(Error::UnexpectedEof))
+}
+
+/// Read the code lengths of a dynamic block, returns its literal and length and its distance decoders.
+fn read_dynamic_codes(reader: &mut LsbReader) -> Result<(Decoder, Decoder)> {
+    let hlit = read_bits(reader, 5)? as usize + 257;
+    let hdist = read_bits(reader, 5)? as usize + 1;
+    let hclen = read_bits(reader, 4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(Error::InvalidPayload(format!("{} literal and length and {} distance codes", hlit, hdist)));
+    }
+    let mut code_len_lengths = [0; 19];
+    for symbol in &CODE_LEN_ORDER[..hclen] {
+        code_len_lengths[*symbol] = read_bits(reader, 3)? as u8;
+    }
+    let code_len_decoder = Decoder::new(&code_len_lengths)?;
+
+    let mut lengths = Vec::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist {
+        let (len, repeat) = match decode(&code_len_decoder, reader)? {
+            16 => match lengths.last() {
+                Some(len) => (*len, 3 + read_bits(reader, 2)?),
+                None      => return Err(Error::InvalidPayload("repeat of no code length".to_string())),
+            },
+            17     => (0, 3 + read_bits(reader, 3)?),
+            18     => (0, 11 + read_bits(reader, 7)?),
+            symbol => (symbol as u8, 1),
+        };
+        if lengths.len() + repeat as usize > hlit + hdist {
+            return Err(Error::InvalidPayload("code lengths repeated past the last code".to_string()));
+        }
+        lengths.extend(std::iter::repeat_n(len, repeat as usize));
+    }
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(Error::InvalidPayload("no code for the end of block".to_string()));
+    }
+    Ok((Decoder::new(&lengths[..hlit])?, Decoder::new(&lengths[hlit..])?))
+}
+
+/// Decode the symbols of a block until its end into `out`.
+fn inflate_codes(reader: &mut LsbReader, litlen: &Decoder, dist: &Decoder, out: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let symbol = decode(litlen, reader)?;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+        let i = symbol as usize - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(Error::InvalidPayload(format!("invalid length symbol {}", symbol)));
+        }
+        let length = LENGTH_BASE[i] as usize + read_bits(reader, LENGTH_EXTRA[i] as u32)? as usize;
+        let i = decode(dist, reader)? as usize;
+        if i >= DIST_BASE.len() {
+            return Err(Error::InvalidPayload(format!("invalid distance symbol {}", i)));
+        }
+        let distance = DIST_BASE[i] as usize + read_bits(reader, DIST_EXTRA[i] as u32)? as usize;
+        if distance > out.len() {
+            return Err(Error::InvalidPayload(format!("distance {} is before the start of the data", distance)));
+        }
+        let start = out.len() - distance;
+        for i in start..start + length {
+            out.push(out[i]);
+        }
+    }
+}
+
+/// Decode the DEFLATE stream at the start of `data`, returns it with the number of bytes read.
+pub fn inflate_prefix(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut reader = LsbReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = read_bits(&mut reader, 1)? == 1;
+        match read_bits(&mut reader, 2)? {
+            0 => {
+                let header = reader.read_bytes(4).ok_or(Error::UnexpectedEof)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+                if len != !nlen {
+                    return Err(Error::InvalidPayload("stored block length does not match its complement".to_string()));
+                }
+                out.extend_from_slice(reader.read_bytes(len as usize).ok_or(Error::UnexpectedEof)?);
+            },
+            1 => {
+                let litlen = Decoder::new(&deflate::fixed_litlen_lengths())?;
+                let dist = Decoder::new(&deflate::fixed_dist_lengths())?;
+                inflate_codes(&mut reader, &litlen, &dist, &mut out)?;
+            },
+            2 => {
+                let (litlen, dist) = read_dynamic_codes(&mut reader)?;
+                inflate_codes(&mut reader, &litlen, &dist, &mut out)?;
+            },
+            _ => return Err(Error::InvalidPayload("invalid block type 3".to_string())),
+        }
+        if last {
+            return Ok((out, reader.position()));
+        }
+    }
+}
+
+/// Decode a raw DEFLATE stream, which must span all of `data`.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let (out, len) = inflate_prefix(data)?;
+    if len != data.len() {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bench;
+    use super::super::deflate::deflate;
+
+    #[test]
+    fn zlib_streams() {
+        // zlib.compressobj(wbits=-15) output: fixed, stored and dynamic blocks
+        assert_eq!(inflate(&[0x03, 0x00]).unwrap(), b"");
+        assert_eq!(inflate(&[0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00]).unwrap(), b"abcabcabcabc");
+        assert_eq!(inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c']).unwrap(), b"abc");
+        let dynamic = [
+            0x2d, 0x8a, 0x81, 0x0d, 0x00, 0x40, 0x10, 0xc1, 0x66, 0x55, 0xf6, 0x9f,
+            0xe1, 0xb9, 0x7c, 0x04, 0x89, 0x02, 0x82, 0xa5, 0x0b, 0x41, 0xc3, 0x68,
+            0xd2, 0x56, 0x17, 0x50, 0x67, 0x74, 0x87, 0x0f, 0x5a, 0xd1, 0x03,
+        ];
+        assert_eq!(inflate(&dynamic).unwrap(), &b"bbbdbcaadbcaaabbaaacbababaacaadcadbbadbdcaaaaaababaacaaabada"[..]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let text: Vec<u8> = (0..20000u32).map(|i| b"etaoin shrdlu"[(i * i % 13) as usize]).collect();
+        let random = bench::random(70000);
+        for data in [&b""[..], b"a", &[0; 100_000], &text, &random] {
+            assert_eq!(inflate(&deflate(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let encoded = deflate(b"abcabcabcabc");
+        assert!(matches!(inflate(&encoded[..encoded.len() - 1]), Err(Error::UnexpectedEof)));
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(inflate(&trailing).is_err());
+        assert_eq!(inflate_prefix(&trailing).unwrap(), (b"abcabcabcabc".to_vec(), encoded.len()));
+        // block type 3
+        assert!(inflate(&[0x07]).is_err());
+        // stored length and complement not matching
+        assert!(inflate(&[0x01, 0x03, 0x00, 0xfc, 0xfe, b'a', b'b', b'c']).is_err());
+        // fixed block with a match 1 byte back from the start
+        assert!(inflate(&[0x03, 0x02, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Description of the content of a compressed file, without decoding its payload.
+
+use std::fmt;
+
+use super::bits::BitSet;
+use super::container::{Method, MAGIC};
+use super::conversion::Table;
+use super::metadata::Metadata;
+
+pub struct Entry {
+    /// Offset of the entry in the file.
+    pub offset: usize,
+    pub symbol: u8,
+    pub code: BitSet,
+}
+
+pub struct Report {
+    pub file_size: usize,
+    pub magic: bool,
+    pub metadata: Option<Metadata>,
+    pub method: Option<Method>,
+    /// `None` if missing or if the method has no table.
+    pub table_size: Option<usize>,
+    pub entries: Vec<Entry>,
+    pub payload_bits: Option<u64>,
+    pub crc: Option<u32>,
+    pub payload_size: usize,
+    /// Everything that would prevent the file from being decoded.
+    pub problems: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    data.get(offset..offset + 8).map(|b| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(b);
+        u64::from_be_bytes(bytes)
+    })
+}
+
+/// Parse as much of the header as possible, recording what is wrong with it.
+pub fn inspect(data: &[u8]) -> Report {
+    let mut report = Report {
+        file_size: data.len(),
+        magic: data.starts_with(MAGIC),
+        metadata: None,
+        method: None,
+        table_size: None,
+        entries: Vec::new(),
+        payload_bits: None,
+        crc: None,
+        payload_size: 0,
+        problems: Vec::new(),
+    };
+    if !report.magic {
+        report.problems.push("missing magic bytes, not a huffman file".to_string());
+    }
+
+    let mut offset = MAGIC.len();
+    match Metadata::deserialize(data.get(offset..).unwrap_or(&[])) {
+        Ok((metadata, size)) => {
+            report.metadata = Some(metadata);
+            offset += size;
+        },
+        Err(e) => {
+            report.problems.push(format!("metadata: {}", e));
+            return report;
+        },
+    }
+    match data.get(offset).map(|b| Method::from_byte(*b)) {
+        Some(Ok(method)) => report.method = Some(method),
+        Some(Err(e)) => {
+            report.problems.push(e.to_string());
+            return report;
+        },
+        None => {
+            report.problems.push("file ends before the method".to_string());
+            return report;
+        },
+    }
+    offset += 1;
+    if report.method != Some(Method::Huffman) {
+        check_payload(data, offset, &mut report);
+        if report.payload_bits.is_some_and(|bits| !bits.is_multiple_of(8)) {
+            report.problems.push("payload is not whole bytes".to_string());
+        }
+        return report;
+    }
+
+    report.table_size = read_u32(data, offset);
+    let table_size = match report.table_size {
+        Some(size) => size,
+        None => {
+            report.problems.push("file ends before the table size".to_string());
+            return report;
+        },
+    };
+    offset += 4;
+    let table_end = offset + table_size;
+    if table_end > data.len() {
+        report.problems.push(format!(
+            "table size {} goes past the end of the file by {} bytes", table_size, table_end - data.len()
+        ));
+    }
+
+    let mut table = Table::new();
+    while offset < table_end.min(data.len()) {
+        let entry_offset = offset;
+        let symbol = data[offset];
+        let bits_len = match data.get(offset + 1) {
+            Some(len) => *len as usize,
+            None => {
+                report.problems.push(format!("entry at offset {} is truncated", entry_offset));
+                break;
+            },
+        };
+        offset += 2;
+        let value_end = offset + bits_len.div_ceil(8);
+        if value_end > table_end || value_end > data.len() {
+            report.problems.push(format!("entry at offset {} is truncated", entry_offset));
+            break;
+        }
+        let code = BitSet { data: data[offset..value_end].to_vec(), len: bits_len };
+        offset = value_end;
+
+        if bits_len == 0 {
+            report.problems.push(format!("entry at offset {} has an empty code", entry_offset));
+        }
+        if table.0.insert(symbol, code.clone()).is_some() {
+            report.problems.push(format!("entry at offset {} redefines symbol {}", entry_offset, symbol));
+        }
+        report.entries.push(Entry { offset: entry_offset, symbol, code });
+    }
+    if let Err(e) = table.validate() {
+        if report.entries.iter().all(|e| e.code.len != 0) {
+            report.problems.push(e.to_string());
+        }
+    }
+
+    check_payload(data, table_end, &mut report);
+    if report.payload_bits.is_some_and(|bits| bits > 0) && table.0.is_empty() {
+        report.problems.push("payload is not empty but the table is".to_string());
+    }
+    report
+}
+
+/// Check the payload size, checksum and payload starting at `offset`.
+fn check_payload(data: &[u8], offset: usize, report: &mut Report) {
+    report.payload_bits = read_u64(data, offset);
+    report.crc = read_u32(data, offset + 8).map(|crc| crc as u32);
+    let payload_bits = match (report.payload_bits, report.crc) {
+        (Some(bits), Some(_)) => bits,
+        _ => {
+            report.problems.push("file ends before the payload size and checksum".to_string());
+            return;
+        },
+    };
+    report.payload_size = data.len() - (offset + 12);
+    let expected_size = payload_bits.div_ceil(8);
+    let payload_size = report.payload_size as u64;
+    if payload_size < expected_size {
+        report.problems.push(format!(
+            "payload is {} bytes but should be {} bytes for {} bits",
+            report.payload_size, expected_size, payload_bits
+        ));
+    } else if payload_size > expected_size {
+        report.problems.push(format!(
+            "{} trailing bytes after the payload", payload_size - expected_size
+        ));
+    } else if !payload_bits.is_multiple_of(8) && data[data.len() - 1] & (0xff >> (payload_bits % 8)) != 0 {
+        report.problems.push("padding bits are not zero".to_string());
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "file size:    {} bytes", self.file_size)?;
+        writeln!(f, "magic:        {}", if self.magic { "ok" } else { "invalid" })?;
+        if let Some(metadata) = &self.metadata {
+            if let Some(name) = &metadata.name {
+                writeln!(f, "name:         {}", name)?;
+            }
+            if let Some(mtime) = metadata.mtime {
+                writeln!(f, "mtime:        {}", mtime)?;
+            }
+            if let Some(mode) = metadata.mode {
+                writeln!(f, "mode:         {:o}", mode)?;
+            }
+            if let Some(size) = metadata.size {
+                writeln!(f, "size:         {} bytes", size)?;
+            }
+        }
+        if let Some(method) = self.method {
+            writeln!(f, "method:       {}", method.name())?;
+        }
+        match self.table_size {
+            Some(size) => writeln!(f, "table size:   {} bytes, {} entries", size, self.entries.len())?,
+            None if self.method.is_some_and(|m| m != Method::Huffman) => {},
+            None       => writeln!(f, "table size:   missing")?,
+        }
+        match self.payload_bits {
+            Some(bits) => writeln!(f, "payload:      {} bits in {} bytes", bits, self.payload_size)?,
+            None       => writeln!(f, "payload:      missing")?,
+        }
+        if let Some(crc) = self.crc {
+            writeln!(f, "crc32:        {:08x}", crc)?;
+        }
+        if !self.entries.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "offset  symbol  length  code")?;
+            for e in &self.entries {
+                writeln!(
+                    f, "{:6}  {:6}  {:6}  {}",
+                    e.offset, format!("{:?}", e.symbol as char), e.code.len, e.code
+                )?;
+            }
+        }
+        if !self.problems.is_empty() {
+            writeln!(f)?;
+            for problem in &self.problems {
+                writeln!(f, "error: {}", problem)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{compress_with, Method, Options};
+
+    fn huffman(data: &[u8]) -> Vec<u8> {
+        compress_with(data, &Options { method: Some(Method::Huffman), ..Options::default() }).unwrap()
+    }
+
+    #[test]
+    fn valid() {
+        let compressed = huffman(b"abracadabra");
+        let report = inspect(&compressed);
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.entries.len(), 5);
+        assert_eq!(report.payload_bits, Some(23));
+        assert_eq!(report.payload_size, 3);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut compressed = huffman(b"abracadabra");
+        compressed[0] = b'X';
+        let report = inspect(&compressed);
+        assert!(!report.magic);
+        assert_eq!(report.problems.len(), 1);
+    }
+
+    #[test]
+    fn truncated_payload() {
+        let compressed = huffman(b"abracadabra");
+        let report = inspect(&compressed[..compressed.len() - 1]);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.entries.len(), 5);
+    }
+
+    #[test]
+    fn truncated_table() {
+        let compressed = huffman(b"abracadabra");
+        let report = inspect(&compressed[..10]);
+        assert!(!report.is_ok());
+        assert!(report.payload_bits.is_none());
+    }
+
+    #[test]
+    fn stored() {
+        let options = Options { method: Some(Method::Stored), ..Options::default() };
+        let compressed = compress_with(b"abracadabra", &options).unwrap();
+        let report = inspect(&compressed);
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.method, Some(Method::Stored));
+        assert_eq!(report.payload_bits, Some(88));
+        assert!(!inspect(&compressed[..compressed.len() - 1]).is_ok());
+    }
+
+    #[test]
+    fn not_prefix_free() {
+        let mut data = MAGIC.to_vec();
+        data.extend(&[0, 0, 0, 0, 0, 6, b'a', 1, 0x80, b'b', 2, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let report = inspect(&data);
+        assert_eq!(report.problems, vec!["invalid table: codes are not prefix-free".to_string()]);
+    }
+}
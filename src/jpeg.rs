@@ -0,0 +1,329 @@
+//! JPEG Huffman tables (ITU T.81 B.2.4.2, Annex C) and entropy coding of symbol streams.
+//!
+//! A DHT segment holds tables as the number of codes of each length from 1 to 16 bits
+//! followed by the symbols in the order of their codes, which are canonical:
+//!
+//! ```text
+//! 2 bytes marker: ff c4
+//! 2 byte unsigned int: size of the segment (including this field)
+//! for each table:
+//!     1 byte: class (0 for DC, 1 for AC) in the high 4 bits, destination identifier in the low 4 bits
+//!     16 bytes: number of codes of each length
+//!     the symbols, 1 byte each
+//! ```
+//!
+//! In entropy-coded data, codes are written most significant bit first, the last byte is
+//! padded with 1 bits and a 0 byte is stuffed after each 0xFF byte so that it is not read as a marker.
+//! The code made of 1 bits only is never assigned, so that padding does not decode as a symbol.
+
+use std::convert::TryFrom;
+
+use super::bits::{BitReader, BitSet, BitWriter};
+use super::canonical;
+use super::conversion::{self, Table, Trie};
+use super::error::{Error, Result};
+
+pub const DHT_MARKER: [u8; 2] = [0xff, 0xc4];
+
+/// Longest code of a JPEG table.
+pub const MAX_CODE_LEN: usize = 16;
+
+/// Huffman table as stored in a DHT segment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dht {
+    /// 0 for DC coefficients, 1 for AC coefficients.
+    pub class: u8,
+    /// Destination identifier, 0 to 3.
+    pub id: u8,
+    /// Number of codes of each length, from 1 to 16 bits.
+    pub counts: [u8; MAX_CODE_LEN],
+    /// Symbols sorted by code.
+    pub symbols: Vec<u8>,
+}
+
+impl Dht {
+    /// Table with the code lengths of `table`, which must be at most 16 bits and not
+    /// use up every code, the codes themselves being made canonical.
+    pub fn from_table(class: u8, id: u8, table: &Table) -> Result<Dht> {
+        let mut symbols: Vec<(usize, u8)> = table.0.iter().map(|(k, v)| (v.len, *k)).collect();
+        symbols.sort_unstable();
+        let mut counts = [0; MAX_CODE_LEN];
+        for (len, symbol) in &symbols {
+            if *len == 0 || *len > MAX_CODE_LEN {
+                return Err(Error::InvalidTable(format!("code of {} bits for {}", len, symbol)));
+            }
+            counts[len - 1] += 1;
+        }
+        let dht = Dht { class, id, counts, symbols: symbols.into_iter().map(|(_, s)| s).collect() };
+        dht.to_table()?;
+        Ok(dht)
+    }
+
+    /// Optimized table for `data` (T.81 K.2): code lengths limited to 16 bits with a symbol of
+    /// the least count added to take the longest code, the one made of 1 bits, and removed.
+    pub fn from_data(class: u8, id: u8, data: &[u8]) -> Dht {
+        let mut counts = vec![0; 257];
+        for byte in data {
+            counts[*byte as usize] += 1;
+        }
+        counts[256] = 1;
+        let mut lengths = canonical::lengths(&counts, MAX_CODE_LEN);
+        // any longest code of a symbol counted once can be swapped with the added one
+        let max_len = *lengths.iter().max().unwrap();
+        if lengths[256] != max_len {
+            let swapped = (0..256).rev().find(|s| lengths[*s] == max_len && counts[*s] == 1).unwrap();
+            lengths.swap(swapped, 256);
+        }
+
+        let mut symbols: Vec<(u8, u8)> = (0..256).filter(|s| lengths[*s] > 0).map(|s| (lengths[s], s as u8)).collect();
+        symbols.sort_unstable();
+        let mut dht_counts = [0; MAX_CODE_LEN];
+        for (len, _) in &symbols {
+            dht_counts[*len as usize - 1] += 1;
+        }
+        Dht { class, id, counts: dht_counts, symbols: symbols.into_iter().map(|(_, s)| s).collect() }
+    }
+
+    /// Table with the canonical code of each symbol (T.81 C.2).
+    pub fn to_table(&self) -> Result<Table> {
+        let total: usize = self.counts.iter().map(|c| *c as usize).sum();
+        if total != self.symbols.len() {
+            return Err(Error::InvalidTable(format!("{} codes for {} symbols", total, self.symbols.len())));
+        }
+        // code length of each symbol, in the order of the symbols
+        let lengths: Vec<u8> = self.counts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, count)| std::iter::repeat_n(i as u8 + 1, *count as usize))
+            .collect();
+        conversion::check_kraft(lengths.iter().map(|len| *len as usize))?;
+        let codes = canonical::codes(&lengths);
+
+        let mut table = Table::new();
+        for ((symbol, code), len) in self.symbols.iter().zip(&codes).zip(&lengths) {
+            if *code == (1 << len) - 1 {
+                return Err(Error::InvalidTable("the code made of 1 bits only is reserved".to_string()));
+            }
+            let mut bits = BitSet::new();
+            for i in (0..*len).rev() {
+                bits.push_bit((code >> i) as u8 & 1);
+            }
+            if table.0.insert(*symbol, bits).is_some() {
+                return Err(Error::InvalidTable(format!("duplicate symbol {}", symbol)));
+            }
+        }
+        Ok(table)
+    }
+
+    fn serialize_to(&self, out: &mut Vec<u8>) {
+        out.push(self.class << 4 | self.id);
+        out.extend_from_slice(&self.counts);
+        out.extend_from_slice(&self.symbols);
+    }
+}
+
+/// DHT segment holding `tables`, starting with its marker.
+/// Its size, which includes the 2 bytes of the size field, must fit in 16 bits.
+pub fn dht_segment(tables: &[Dht]) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    for table in tables {
+        table.serialize_to(&mut body);
+    }
+    let size = u16::try_from(body.len() + 2)
+        .map_err(|_| Error::InvalidTable(format!("{} bytes of tables do not fit in a DHT segment", body.len())))?;
+    let mut out = DHT_MARKER.to_vec();
+    out.extend_from_slice(&size.to_be_bytes());
+    out.extend(body);
+    Ok(out)
+}
+
+/// Parse a DHT segment at the start of `data`, starting with its marker,
+/// returns its tables with the number of bytes read.
+pub fn parse_dht_segment(data: &[u8]) -> Result<(Vec<Dht>, usize)> {
+    if data.len() < 4 {
+        return Err(Error::UnexpectedEof);
+    }
+    if data[..2] != DHT_MARKER {
+        return Err(Error::InvalidHeader(format!("marker {:02x}{:02x} is not DHT", data[0], data[1])));
+    }
+    let size = u16::from_be_bytes([data[2], data[3]]) as usize;
+    if size < 2 {
+        return Err(Error::InvalidHeader(format!("segment size {}", size)));
+    }
+    let end = 2 + size;
+    let body = data.get(4..end).ok_or(Error::UnexpectedEof)?;
+    let mut tables = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        let header = body.get(i..i + 1 + MAX_CODE_LEN).ok_or(Error::UnexpectedEof)?;
+        let (class, id) = (header[0] >> 4, header[0] & 0x0f);
+        if class > 1 || id > 3 {
+            return Err(Error::InvalidHeader(format!("table class {} and identifier {}", class, id)));
+        }
+        let mut counts = [0; MAX_CODE_LEN];
+        counts.copy_from_slice(&header[1..]);
+        i += 1 + MAX_CODE_LEN;
+        let total: usize = counts.iter().map(|c| *c as usize).sum();
+        let symbols = body.get(i..i + total).ok_or(Error::UnexpectedEof)?.to_vec();
+        i += total;
+        let table = Dht { class, id, counts, symbols };
+        table.to_table()?;
+        tables.push(table);
+    }
+    Ok((tables, end))
+}
+
+/// Code `symbols` as JPEG entropy-coded data.
+pub fn encode(table: &Table, symbols: &[u8]) -> Result<Vec<u8>> {
+    let mut writer = BitWriter::new(Vec::new());
+    table.encode_to(symbols, &mut writer)?;
+    let padding = ((8 - writer.len() % 8) % 8) as u32;
+    writer.write_bits(u64::MAX, padding)?;
+    let mut out = Vec::new();
+    for byte in writer.finish()? {
+        out.push(byte);
+        if byte == 0xff {
+            out.push(0);
+        }
+    }
+    Ok(out)
+}
+
+/// Decode JPEG entropy-coded data up to the end of `data` or the first marker,
+/// returns the symbols with the number of bytes read. No code of `table` may be made of 1 bits only,
+/// as for the tables of `Dht::to_table`.
+pub fn decode(table: &Table, data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == 0xff {
+            match data.get(i + 1) {
+                Some(0) => i += 1,
+                Some(_) => break,
+                None    => return Err(Error::UnexpectedEof),
+            }
+            bytes.push(0xff);
+        } else {
+            bytes.push(data[i]);
+        }
+        i += 1;
+    }
+
+    let trie = Trie::new(table)?;
+    let mut reader = BitReader::new(&bytes[..]);
+    let mut symbols = Vec::new();
+    let mut left = bytes.len() * 8;
+    while left > 0 {
+        // codes are never made of 1 bits only, so these are the padding
+        let mask = ((1u16 << left.min(8)) - 1) as u8;
+        if left < 8 && bytes[bytes.len() - 1] & mask == mask {
+            break;
+        }
+        let symbol = trie.read_symbol(&mut reader)?;
+        left -= table.0[&symbol].len;
+        symbols.push(symbol);
+    }
+    Ok((symbols, i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Table for the luminance DC differences of T.81 K.3.
+    fn luminance_dc() -> Dht {
+        Dht {
+            class: 0,
+            id: 0,
+            counts: [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+            symbols: (0..12).collect(),
+        }
+    }
+
+    #[test]
+    fn standard_table() {
+        let table = luminance_dc().to_table().unwrap();
+        let codes = [
+            "00", "010", "011", "100", "101", "110", "1110", "11110", "111110", "1111110", "11111110", "111111110",
+        ];
+        for (symbol, code) in codes.iter().enumerate() {
+            assert_eq!(table.0[&(symbol as u8)].to_string(), *code);
+        }
+        assert_eq!(Dht::from_table(0, 0, &table).unwrap(), luminance_dc());
+    }
+
+    #[test]
+    fn segment_round_trip() {
+        let ac = Dht::from_data(1, 1, b"abracadabra");
+        let segment = dht_segment(&[luminance_dc(), ac.clone()]).unwrap();
+        assert_eq!(&segment[..4], &[0xff, 0xc4, 0, 2 + 17 + 12 + 17 + 5]);
+        assert_eq!(segment[4], 0x00);
+        assert_eq!(segment[4 + 17 + 12], 0x11);
+        let mut data = segment.clone();
+        data.extend_from_slice(&[0xff, 0xda]);
+        assert_eq!(parse_dht_segment(&data).unwrap(), (vec![luminance_dc(), ac], segment.len()));
+    }
+
+    #[test]
+    fn optimized_tables() {
+        let dht = Dht::from_data(0, 0, b"aaaab");
+        assert_eq!(dht.symbols, vec![b'a', b'b']);
+        assert_eq!(dht.counts[..2], [1, 1]);
+        // every symbol counted once, which would take every code of 8 bits without the added one
+        let all: Vec<u8> = (0..=255).collect();
+        let dht = Dht::from_data(1, 0, &all);
+        assert!(dht.to_table().is_ok());
+        assert_eq!(dht.counts[7..9], [255, 1]);
+        // Fibonacci counts would give codes longer than 16 bits
+        let mut skewed = Vec::new();
+        let (mut a, mut b) = (1, 1);
+        for symbol in 0..24 {
+            skewed.extend(vec![symbol; a]);
+            (a, b) = (b, a + b);
+        }
+        let table = Dht::from_data(1, 0, &skewed).to_table().unwrap();
+        assert!(table.0.values().all(|code| code.len <= MAX_CODE_LEN));
+    }
+
+    #[test]
+    fn entropy_coding() {
+        let table = luminance_dc().to_table().unwrap();
+        let symbols = [11, 11, 0, 1, 7, 7, 7, 7];
+        // 111111110 111111110 00 010 11110 11110 11110 11110 and 1 bits
+        let encoded = encode(&table, &symbols).unwrap();
+        assert_eq!(encoded, vec![0xff, 0x00, 0x7f, 0x85, 0xef, 0x7b, 0xdf]);
+        assert_eq!(decode(&table, &encoded).unwrap(), (symbols.to_vec(), encoded.len()));
+        let mut with_marker = encoded.clone();
+        with_marker.extend_from_slice(&[0xff, 0xd9]);
+        assert_eq!(decode(&table, &with_marker).unwrap(), (symbols.to_vec(), encoded.len()));
+        assert_eq!(decode(&table, &encode(&table, &[]).unwrap()).unwrap(), (vec![], 0));
+    }
+
+    #[test]
+    fn errors() {
+        let mut oversubscribed = luminance_dc();
+        oversubscribed.counts[1] = 6;
+        oversubscribed.symbols.push(12);
+        assert!(oversubscribed.to_table().is_err());
+        let mut all_ones = luminance_dc();
+        all_ones.symbols.truncate(2);
+        all_ones.counts = [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(all_ones.to_table().is_err());
+        let mut duplicate = luminance_dc();
+        duplicate.symbols[1] = 0;
+        assert!(duplicate.to_table().is_err());
+        assert!(parse_dht_segment(&[0xff, 0xc4, 0, 19, 0x00, 1]).is_err());
+        assert!(parse_dht_segment(&[0xff, 0xdb, 0, 2]).is_err());
+        // 240 tables of 273 bytes, one more than fits
+        let full = Dht::from_data(1, 0, &(0..=255).collect::<Vec<u8>>());
+        assert_eq!(dht_segment(&vec![full.clone(); 240]).unwrap().len(), 4 + 240 * 273);
+        assert!(matches!(dht_segment(&vec![full; 241]), Err(Error::InvalidTable(_))));
+
+        let table = luminance_dc().to_table().unwrap();
+        assert_eq!(decode(&table, &[0x00]).unwrap().0, vec![0; 4]);
+        // padding with 0 bits
+        assert!(decode(&table, &[0x01]).is_err());
+        // 0xff byte without the stuffed 0
+        assert!(decode(&table, &[0xff]).is_err());
+    }
+}
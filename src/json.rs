@@ -0,0 +1,301 @@
+//! Minimal JSON reader and string escaping, enough for the textual formats of this crate.
+
+use super::error::{Error, Result};
+
+/// Deepest nesting of arrays and objects accepted, so that the recursion cannot overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// Kept as written so that integers do not lose precision.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _                      => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _                => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _                => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _                    => None,
+        }
+    }
+}
+
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c    => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn parse(s: &str) -> Result<Value> {
+    let mut parser = Parser { s: s.as_bytes(), i: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.i != parser.s.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+    /// Number of arrays and objects the parser is in.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> Error {
+        Error::Parse(format!("{} at offset {}", message, self.i))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.i < self.s.len() && matches!(self.s[self.i], b' ' | b'\t' | b'\n' | b'\r') {
+            self.i += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.s.get(self.i).cloned()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c as char)));
+        }
+        self.i += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        if !self.s[self.i..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected character"));
+        }
+        self.i += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            None         => Err(self.error("unexpected end of input")),
+            Some(b'n')   => self.literal("null", Value::Null),
+            Some(b't')   => self.literal("true", Value::Bool(true)),
+            Some(b'f')   => self.literal("false", Value::Bool(false)),
+            Some(b'"')   => Ok(Value::String(self.string()?)),
+            Some(b'[')   => self.nested(Parser::array),
+            Some(b'{')   => self.nested(Parser::object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_)      => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value>) -> Result<Value> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested more than {} levels deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.i;
+        while self.i < self.s.len() && matches!(self.s[self.i], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.i += 1;
+        }
+        let n = std::str::from_utf8(&self.s[start..self.i]).unwrap();
+        if n.parse::<f64>().is_err() {
+            return Err(self.error("invalid number"));
+        }
+        Ok(Value::Number(n.to_string()))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let c = match self.s.get(self.i) {
+                Some(c) => *c,
+                None    => return Err(self.error("unterminated string")),
+            };
+            self.i += 1;
+            match c {
+                b'"'  => break,
+                b'\\' => {
+                    let escaped = match self.s.get(self.i) {
+                        Some(c) => *c,
+                        None    => return Err(self.error("unterminated string")),
+                    };
+                    self.i += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => out.push(escaped),
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'u' => {
+                            let hex = self.s.get(self.i..self.i + 4)
+                                .and_then(|h| std::str::from_utf8(h).ok())
+                                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                                .and_then(char::from_u32);
+                            match hex {
+                                Some(c) => out.extend(c.to_string().as_bytes()),
+                                None    => return Err(self.error("invalid unicode escape")),
+                            }
+                            self.i += 4;
+                        },
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                },
+                c => out.push(c),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid utf-8"))
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(b']') {
+            self.i += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.i += 1,
+                Some(b']') => { self.i += 1; break; },
+                _          => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.i += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.i += 1,
+                Some(b'}') => { self.i += 1; break; },
+                _          => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+        Ok(Value::Object(members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nested() {
+        let value = parse(" {\"a\": [1, 2.5, -3e2], \"b\": {\"c\": null, \"d\": true}, \"e\": \"x\\\"y\\u0041\"} ").unwrap();
+        assert_eq!(value.get("a").unwrap().as_array().unwrap().len(), 3);
+        assert_eq!(value.get("a").unwrap().as_array().unwrap()[0].as_u64(), Some(1));
+        assert_eq!(value.get("b").unwrap().get("d"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("e").unwrap().as_str(), Some("x\"yA"));
+    }
+
+    #[test]
+    fn parse_large_integer() {
+        assert_eq!(parse("18446744073709551615").unwrap().as_u64(), Some(u64::MAX));
+    }
+
+    fn error(s: &str) -> String {
+        match parse(s) {
+            Err(Error::Parse(message)) => message,
+            Err(e)                     => panic!("unexpected error {}", e),
+            Ok(_)                      => panic!("parsed {:?}", s),
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(error(""), "unexpected end of input at offset 0");
+        assert_eq!(error("[1, 2"), "expected ',' or ']' at offset 5");
+        assert_eq!(error("{\"a\": 1 \"b\": 2}"), "expected ',' or '}' at offset 8");
+        assert_eq!(error("{\"a\" 1}"), "expected ':' at offset 5");
+        assert_eq!(error("{1: 2}"), "expected '\"' at offset 1");
+        assert_eq!(error("[1] 2"), "trailing characters at offset 4");
+        assert_eq!(error("tru"), "unexpected character at offset 0");
+        assert_eq!(error("[@]"), "unexpected character at offset 1");
+        assert_eq!(error("1-2"), "invalid number at offset 3");
+    }
+
+    #[test]
+    fn string_errors() {
+        assert_eq!(error("\"abc"), "unterminated string at offset 4");
+        assert_eq!(error("\"abc\\"), "unterminated string at offset 5");
+        assert_eq!(error("\"\\x\""), "invalid escape at offset 3");
+        assert_eq!(error("\"\\u12\""), "invalid unicode escape at offset 3");
+        // a lone surrogate is not a character
+        assert_eq!(error("\"\\ud800\""), "invalid unicode escape at offset 3");
+    }
+
+    #[test]
+    fn parse_depth() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(parse(&nested(MAX_DEPTH + 1)), Err(Error::Parse(_))));
+        let objects = format!("{}1{}", "{\"a\":".repeat(MAX_DEPTH + 1), "}".repeat(MAX_DEPTH + 1));
+        assert!(matches!(parse(&objects), Err(Error::Parse(_))));
+        // deep enough to overflow the stack without the limit
+        assert!(matches!(parse(&"[".repeat(200_000)), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let s = "a\"b\\c\nd\u{1}";
+        assert_eq!(parse(&escape(s)).unwrap().as_str(), Some(s));
+    }
+}
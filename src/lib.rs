@@ -0,0 +1,34 @@
+pub mod tree;
+pub mod bits;
+pub mod checksum;
+pub mod conversion;
+pub mod codebook;
+pub mod container;
+pub mod archive;
+pub mod error;
+pub mod metadata;
+pub mod json;
+pub mod inspect;
+pub mod stats;
+pub mod spool;
+pub mod mmap;
+pub mod bench;
+pub mod rle;
+pub mod bwt;
+pub mod canonical;
+pub mod deflate;
+pub mod inflate;
+pub mod lz77;
+pub mod frequencies;
+pub mod range;
+pub mod tans;
+pub mod shannon_fano;
+pub mod tunstall;
+pub mod zlib;
+pub mod gzip;
+pub mod format;
+pub mod jpeg;
+pub mod hpack;
+
+pub use container::{compress, decompress, verify};
+pub use error::{Error, Result};
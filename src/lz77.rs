@@ -0,0 +1,324 @@
+//! LZ77 followed by Huffman coding, for data made of repeated strings.
+//!
+//! The data is split in tokens, either a literal byte or a match copying `length` bytes
+//! starting `distance` bytes back, found with hash chains over a sliding window.
+//! Literals and match lengths share one table, the lengths coming after the 256 bytes,
+//! and distances are coded as their number of bits in a second table followed by the
+//! bits after the leading one, as in DEFLATE:
+//!
+//! ```text
+//! 4 byte unsigned int: literals and lengths table size (excluding this field)
+//! literals and lengths table, as in a compressed file but with 2 byte unsigned int symbols:
+//!     2 byte unsigned int: symbol, the byte of a literal or 256 plus a length minus 3
+//!     1 byte for size of representation in bits
+//!     the representation aligned on a 8-bit boundary
+//! table of the distances (as in a compressed file)
+//! 8 byte unsigned int: number of tokens
+//! codes of each token, padded with zeroes to a 8-bit boundary
+//! ```
+
+use super::bits::{BitReader, BitWriter};
+use super::conversion::{Table, Trie};
+use super::error::{Error, Result};
+
+/// Default size of the window matches are searched in, as DEFLATE's.
+pub const WINDOW_SIZE: usize = 1 << 15;
+
+/// Largest window, so that distance codes stay below 25.
+pub const MAX_WINDOW_SIZE: usize = 1 << 24;
+
+pub const MIN_MATCH: usize = 3;
+
+/// Longest match, so that its length minus `MIN_MATCH` fits in a byte.
+pub const MAX_MATCH: usize = MIN_MATCH + 255;
+
+/// Number of earlier positions with the same hash tried for each match.
+const MAX_CHAIN: usize = 64;
+
+const HASH_BITS: u32 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    Literal(u8),
+    Match { length: usize, distance: usize },
+}
+
+impl Token {
+    /// Symbol of the token in the literals and lengths table.
+    fn symbol(self) -> u16 {
+        match self {
+            Token::Literal(b)           => b as u16,
+            Token::Match { length, .. } => (256 + length - MIN_MATCH) as u16,
+        }
+    }
+}
+
+/// Distance code, the number of bits of `distance - 1`, with the bits after the leading one.
+fn distance_code(distance: usize) -> (u8, u64, u32) {
+    let d = (distance - 1) as u64;
+    let code = 64 - d.leading_zeros();
+    let extra = code.saturating_sub(1);
+    (code as u8, d & ((1 << extra) - 1), extra)
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Split `data` in literals and matches no further back than `window_size` bytes.
+///
+/// Matches are chosen greedily, the longest of the positions in the hash chain of the next
+/// 3 bytes, each chain being linked through a table with a slot per position of the window.
+pub fn tokens(data: &[u8], window_size: usize) -> Vec<Token> {
+    let window_size = window_size.clamp(1, MAX_WINDOW_SIZE);
+    // positions plus one, 0 ending a chain
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut prev = vec![0usize; window_size.min(data.len())];
+    // positions followed by enough bytes to be hashed
+    let hashed = data.len().saturating_sub(MIN_MATCH - 1);
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut length, mut distance) = (0, 0);
+        if pos < hashed {
+            let max = (data.len() - pos).min(MAX_MATCH);
+            let mut candidate = head[hash(&data[pos..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == 0 || pos - (candidate - 1) > window_size {
+                    break;
+                }
+                let start = candidate - 1;
+                let len = data[start..].iter().zip(&data[pos..pos + max]).take_while(|(a, b)| a == b).count();
+                if len > length {
+                    length = len;
+                    distance = pos - start;
+                    if len == max {
+                        break;
+                    }
+                }
+                let next = prev[start % window_size];
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+        if length >= MIN_MATCH {
+            tokens.push(Token::Match { length, distance });
+        } else {
+            length = 1;
+            tokens.push(Token::Literal(data[pos]));
+        }
+        for p in pos..(pos + length).min(hashed) {
+            let h = hash(&data[p..]);
+            prev[p % window_size] = head[h];
+            head[h] = p + 1;
+        }
+        pos += length;
+    }
+    tokens
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    encode_with_window(data, WINDOW_SIZE)
+}
+
+/// Encode with matches no further back than `window_size` bytes.
+pub fn encode_with_window(data: &[u8], window_size: usize) -> Vec<u8> {
+    let tokens = tokens(data, window_size);
+    let symbols = Table::from_symbols(tokens.iter().map(|t| t.symbol()));
+    let distances = Table::from_symbols(tokens.iter().filter_map(|t| match t {
+        Token::Match { distance, .. } => Some(distance_code(*distance).0),
+        Token::Literal(_)             => None,
+    }));
+
+    let mut out = symbols.serialize();
+    out.extend(distances.serialize());
+    out.extend_from_slice(&(tokens.len() as u64).to_be_bytes());
+    let mut writer = BitWriter::new(out);
+    for token in &tokens {
+        // writing to a vector cannot fail
+        writer.write_bitset(&symbols.0[&token.symbol()]).unwrap();
+        if let Token::Match { distance, .. } = token {
+            let (code, extra, extra_len) = distance_code(*distance);
+            writer.write_bitset(&distances.0[&code]).unwrap();
+            writer.write_bits(extra, extra_len).unwrap();
+        }
+    }
+    writer.finish().unwrap()
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let (symbols, symbols_size) = Table::<u16>::deserialize(data)?;
+    if let Some(symbol) = symbols.0.keys().find(|s| **s as usize > 256 + MAX_MATCH - MIN_MATCH) {
+        return Err(Error::InvalidHeader(format!("invalid symbol {}", symbol)));
+    }
+    let (distances, distances_size) = Table::<u8>::deserialize(&data[symbols_size..])?;
+    let data = &data[symbols_size + distances_size..];
+    let count = data.get(..8).ok_or(Error::UnexpectedEof)?;
+    let count = u64::from_be_bytes([count[0], count[1], count[2], count[3], count[4], count[5], count[6], count[7]]);
+
+    let (symbols, distances) = (Trie::new(&symbols)?, Trie::new(&distances)?);
+    let mut reader = BitReader::new(&data[8..]);
+    let mut out = Vec::new();
+    for _ in 0..count {
+        let symbol = symbols.read_symbol(&mut reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        let length = symbol - 256 + MIN_MATCH;
+        let code = distances.read_symbol(&mut reader)? as u32;
+        if code > MAX_WINDOW_SIZE.trailing_zeros() {
+            return Err(Error::InvalidPayload(format!("invalid distance code {}", code)));
+        }
+        let mut d = if code == 0 { 0 } else { 1 };
+        for _ in 0..code.saturating_sub(1) {
+            d = d << 1 | reader.read_bit()?.ok_or(Error::UnexpectedEof)? as usize;
+        }
+        let distance = d + 1;
+        if distance > out.len() {
+            return Err(Error::InvalidPayload(format!("distance {} is before the start of the data", distance)));
+        }
+        let start = out.len() - distance;
+        for i in start..start + length {
+            out.push(out[i]);
+        }
+    }
+    if reader.remaining_in_byte() != 0 {
+        return Err(Error::InvalidPayload("padding bits are not zero".to_string()));
+    }
+    if reader.has_more_bytes()? {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bits::BitSet;
+
+    fn round_trip(data: &[u8]) {
+        assert_eq!(decode(&encode(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn split_tokens() {
+        assert_eq!(tokens(b"abcabcabcd", WINDOW_SIZE), vec![
+            Token::Literal(b'a'),
+            Token::Literal(b'b'),
+            Token::Literal(b'c'),
+            Token::Match { length: 6, distance: 3 },
+            Token::Literal(b'd'),
+        ]);
+        assert_eq!(tokens(&[0; 300], WINDOW_SIZE), vec![
+            Token::Literal(0),
+            Token::Match { length: MAX_MATCH, distance: 1 },
+            Token::Match { length: 41, distance: 1 },
+        ]);
+        assert!(tokens(b"", WINDOW_SIZE).is_empty());
+    }
+
+    #[test]
+    fn window() {
+        let mut data = b"0123456789".to_vec();
+        data.extend(vec![b'-'; 20]);
+        data.extend(b"0123456789");
+        assert!(tokens(&data, 64).contains(&Token::Match { length: 10, distance: 30 }));
+        assert!(!tokens(&data, 16).iter().any(|t| matches!(t, Token::Match { distance, .. } if *distance > 16)));
+        assert_eq!(decode(&encode_with_window(&data, 16)).unwrap(), data);
+    }
+
+    #[test]
+    fn distance_codes() {
+        assert_eq!(distance_code(1), (0, 0, 0));
+        assert_eq!(distance_code(2), (1, 0, 0));
+        assert_eq!(distance_code(3), (2, 0, 1));
+        assert_eq!(distance_code(4), (2, 1, 1));
+        assert_eq!(distance_code(1 << 15), (15, (1 << 14) - 1, 14));
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"");
+        round_trip(b"a");
+        round_trip(b"abracadabra");
+        round_trip(&[7; 1000]);
+        let log: Vec<u8> = (0..200)
+            .flat_map(|i| format!("{{\"id\": {}, \"level\": \"info\", \"msg\": \"request {}\"}}\n", i, i % 17).into_bytes())
+            .collect();
+        round_trip(&log);
+        assert!(encode(&log).len() < log.len() / 4);
+    }
+
+    fn bits(code: &str) -> BitSet {
+        let mut bits = BitSet::new();
+        for c in code.chars() {
+            bits.push_bit(if c == '1' { 1 } else { 0 });
+        }
+        bits
+    }
+
+    /// `count` tokens coded with the given literals and lengths codes and distance codes.
+    fn stream(codes: &[(u16, &str)], distances: &[(u8, &str)], count: u64, payload: &[u8]) -> Vec<u8> {
+        let codes = Table(codes.iter().map(|(k, v)| (*k, bits(v))).collect());
+        let distances = Table(distances.iter().map(|(k, v)| (*k, bits(v))).collect());
+        let mut out = codes.serialize();
+        out.extend(distances.serialize());
+        out.extend_from_slice(&count.to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn error(data: &[u8]) -> String {
+        decode(data).expect_err("decoded").to_string()
+    }
+
+    #[test]
+    fn table_errors() {
+        let one = (b'a' as u16, "0");
+        assert_eq!(error(&stream(&[one, (256 + 256, "1")], &[], 0, &[])), "invalid header: invalid symbol 512");
+        assert_eq!(error(&stream(&[one, (98, "")], &[], 0, &[])), "invalid header: empty code for 98");
+        assert_eq!(error(&[0, 0, 0, 6, 0, 97, 1, 0x00, 0, 97]), "invalid header: truncated table entry");
+        assert_eq!(
+            error(&[0, 0, 0, 8, 0, 97, 1, 0x00, 0, 97, 1, 0x80]),
+            "invalid header: duplicate code for 97"
+        );
+        assert_eq!(
+            error(&[0, 0, 0, 12, 0, 97, 1, 0x00, 0, 98, 1, 0x80, 0, 99, 1, 0x80]),
+            "invalid table: code lengths are oversubscribed"
+        );
+        // the distances table is checked as any other
+        assert_eq!(
+            error(&stream(&[one], &[(0, "0"), (1, "0")], 0, &[])),
+            "invalid table: codes are not prefix-free"
+        );
+        assert!(matches!(decode(&stream(&[one], &[], 0, &[])[..10]), Err(Error::UnexpectedEof)));
+    }
+
+    #[test]
+    fn payload_errors() {
+        // literal 'a' and matches of 6 bytes
+        let codes = [(b'a' as u16, "0"), (256 + 6 - MIN_MATCH as u16, "1")];
+        // 'a' then a match of distance code 25, past the largest window
+        assert_eq!(
+            error(&stream(&codes, &[(25, "0")], 2, &[0b0100_0000])),
+            "invalid payload: invalid distance code 25"
+        );
+        // a match of distance 9 after a single literal
+        assert_eq!(
+            error(&stream(&codes, &[(4, "0")], 2, &[0b0100_0000])),
+            "invalid payload: distance 9 is before the start of the data"
+        );
+        // more tokens than the payload holds
+        assert!(matches!(decode(&stream(&codes, &[(0, "0")], 9, &[0])), Err(Error::UnexpectedEof)));
+        assert_eq!(
+            error(&stream(&codes, &[(0, "0")], 2, &[0b0100_0000, 0])),
+            "invalid payload: trailing bytes after payload"
+        );
+        assert_eq!(decode(&stream(&codes, &[(0, "0")], 2, &[0b0100_0000])).unwrap(), [b'a'; 7]);
+    }
+}
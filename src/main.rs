@@ -1,89 +1,662 @@
 use std::env;
-// use std::fs;
+use std::fs;
 use std::io;
 use std::io::Read;
 use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
 
-pub mod tree;
-pub mod bits;
-pub mod conversion;
+use huffman::archive;
+use huffman::bench::{self, Bench};
+use huffman::codebook::Codebook;
+use huffman::container::{self, Method, SUFFIX};
+use huffman::format::{self, Format};
+use huffman::gzip;
+use huffman::inspect;
+use huffman::lz77;
+use huffman::metadata::Metadata;
+use huffman::mmap::Mmap;
+use huffman::spool::{self, Spool};
+use huffman::stats::Stats;
+use huffman::tree::Tree;
+use huffman::zlib;
 
-use tree::Tree;
-use bits::BitSet;
+const USAGE: &str = "\
+Usage: huffman [COMMAND] [OPTIONS] [FILE...]
+       huffman add|list|extract [OPTIONS] ARCHIVE [FILE...]
 
-fn main() {
-    if let Some(s) = env::args().nth(1) {
-        if s != "d" {
-            return
+Commands:
+  compress           compress FILEs to FILE.huffman (default)
+  decompress, d      decompress FILE.huffman, FILE.gz or FILE.zz to FILE
+  cat                decompress FILEs to standard output
+  stats              print statistics on the coding of FILEs
+  tree               print the Huffman tree of FILEs
+  table              print the code table of FILEs
+  inspect            print the header of compressed FILEs
+  verify, test       check compressed FILEs decode correctly
+  bench              measure the speed and ratio of the coding of FILEs,
+                     or of built-in corpora without FILE
+  add                add FILEs and directories to ARCHIVE, creating it if needed
+  list               list the files in ARCHIVE
+  extract            extract FILEs, or all files, from ARCHIVE
+  help               print this help
+
+Options:
+  -c, --stdout       write to standard output and keep input files
+  -o, --output FILE  write output to FILE, or extract to directory FILE
+  -k, --keep         keep input files
+  -f, --force        overwrite existing output files
+  -n, --no-name      do not store the original file name and modification time
+  -N, --name         restore the original file name, modification time and permissions
+      --json         print statistics or benchmarks as JSON
+      --iterations N run each step of a benchmark N times (default 5)
+      --format FMT   print the tree as text (default) or dot,
+                     print the table as csv (default) or json
+      --table FILE   compress with the code table in FILE (csv or json)
+      --method NAME  compress with huffman, stored, rle, bwt, lz77, range or tans
+                     (default: huffman unless stored is smaller),
+                     all but huffman and stored hold the whole input in memory
+      --window N     search LZ77 matches N bytes back (default 32768, at most 16777216)
+      --solid        create an archive sharing one code table between its files
+  -h, --help         print this help
+
+With no FILE, or when FILE is -, read standard input and write standard output.
+Decompressing also reads gzip and zlib data, recognized by their first bytes.";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Compress,
+    Decompress,
+    Cat,
+    Stats,
+    Tree,
+    Table,
+    Inspect,
+    Verify,
+    Bench,
+    Add,
+    List,
+    Extract,
+    Help,
+}
+
+#[derive(Default)]
+struct Options {
+    stdout: bool,
+    output: Option<PathBuf>,
+    keep: bool,
+    force: bool,
+    json: bool,
+    format: Option<String>,
+    table: Option<PathBuf>,
+    method: Option<Method>,
+    solid: bool,
+    iterations: Option<usize>,
+    window: Option<usize>,
+    name: bool,
+    no_name: bool,
+    files: Vec<String>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<(Command, Options), String> {
+    let mut args = args.peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("compress")         => Some(Command::Compress),
+        Some("decompress" | "d") => Some(Command::Decompress),
+        Some("cat")              => Some(Command::Cat),
+        Some("stats")            => Some(Command::Stats),
+        Some("tree")             => Some(Command::Tree),
+        Some("table")            => Some(Command::Table),
+        Some("inspect")          => Some(Command::Inspect),
+        Some("verify" | "test")  => Some(Command::Verify),
+        Some("bench")            => Some(Command::Bench),
+        Some("add")              => Some(Command::Add),
+        Some("list")             => Some(Command::List),
+        Some("extract")          => Some(Command::Extract),
+        Some("help")             => Some(Command::Help),
+        _                        => None,
+    };
+    if command.is_some() {
+        args.next();
+    }
+    let command = command.unwrap_or(Command::Compress);
+
+    let mut options = Options::default();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files || arg == "-" || !arg.starts_with('-') {
+            options.files.push(arg);
+            continue;
+        }
+        let flags: Vec<String> = match arg.strip_prefix("--") {
+            Some("")   => { only_files = true; continue; },
+            Some(long) => vec![long.to_string()],
+            None       => arg[1..].chars().map(|c| c.to_string()).collect(),
+        };
+        for flag in flags {
+            match flag.as_str() {
+                "c" | "stdout" => options.stdout = true,
+                "k" | "keep"   => options.keep = true,
+                "f" | "force"  => options.force = true,
+                "n" | "no-name" => options.no_name = true,
+                "N" | "name"   => options.name = true,
+                "json"         => options.json = true,
+                "solid"        => options.solid = true,
+                "h" | "help"   => return Ok((Command::Help, options)),
+                "o" | "output" => match args.next() {
+                    Some(path) => options.output = Some(PathBuf::from(path)),
+                    None       => return Err(format!("option '{}' requires an argument", arg)),
+                },
+                "format" => match args.next() {
+                    Some(format) => options.format = Some(format),
+                    None         => return Err(format!("option '{}' requires an argument", arg)),
+                },
+                "table" => match args.next() {
+                    Some(path) => options.table = Some(PathBuf::from(path)),
+                    None       => return Err(format!("option '{}' requires an argument", arg)),
+                },
+                "method" => match args.next() {
+                    Some(name) => match Method::from_name(&name) {
+                        Some(method) => options.method = Some(method),
+                        None         => return Err(format!("unknown method '{}'", name)),
+                    },
+                    None => return Err(format!("option '{}' requires an argument", arg)),
+                },
+                "iterations" => match args.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) if n > 0 => options.iterations = Some(n),
+                    Some(_)              => return Err(format!("invalid argument to option '{}'", arg)),
+                    None                 => return Err(format!("option '{}' requires an argument", arg)),
+                },
+                "window" => match args.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) if n > 0 && n <= lz77::MAX_WINDOW_SIZE => options.window = Some(n),
+                    Some(_) => return Err(format!("invalid argument to option '{}'", arg)),
+                    None    => return Err(format!("option '{}' requires an argument", arg)),
+                },
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
+        }
+    }
+    if options.json && !matches!(command, Command::Stats | Command::Bench) {
+        return Err("option '--json' is only valid with stats and bench".to_string());
+    }
+    if options.iterations.is_some() && command != Command::Bench {
+        return Err("option '--iterations' is only valid with bench".to_string());
+    }
+    match (&options.format, command) {
+        (None, _) => {},
+        (Some(format), Command::Tree) if format == "text" || format == "dot" => {},
+        (Some(format), Command::Tree) => return Err(format!("unknown tree format '{}'", format)),
+        (Some(format), Command::Table) if format == "csv" || format == "json" => {},
+        (Some(format), Command::Table) => return Err(format!("unknown table format '{}'", format)),
+        (Some(_), _) => return Err("option '--format' is only valid with tree and table".to_string()),
+    }
+    if options.table.is_some() && command != Command::Compress {
+        return Err("option '--table' is only valid with compress".to_string());
+    }
+    if options.method.is_some() && command != Command::Compress {
+        return Err("option '--method' is only valid with compress".to_string());
+    }
+    if options.table.is_some() && options.method.is_some_and(|m| m != Method::Huffman) {
+        return Err("option '--table' is only valid with the huffman method".to_string());
+    }
+    if options.window.is_some() && options.method != Some(Method::Lz77) {
+        return Err("option '--window' is only valid with the lz77 method".to_string());
+    }
+    if options.solid && command != Command::Add {
+        return Err("option '--solid' is only valid with add".to_string());
+    }
+    if command == Command::Bench {
+        if options.output.is_some() || options.stdout || options.keep || options.force || options.name || options.no_name {
+            return Err("options '-o', '-c', '-k', '-f', '-n' and '-N' are not valid with bench".to_string());
+        }
+        return Ok((command, options));
+    }
+    if matches!(command, Command::Add | Command::List | Command::Extract) {
+        if options.files.is_empty() {
+            return Err("missing archive operand".to_string());
+        }
+        if options.output.is_some() && command != Command::Extract {
+            return Err("option '-o' is not valid with add and list".to_string());
         }
-        let data: Vec<u8> = io::stdin().bytes().map(|x| x.unwrap()).collect();
+        return Ok((command, options));
+    }
+    if options.output.is_some() && options.files.len() > 1 {
+        return Err("option '-o' cannot be used with multiple files".to_string());
+    }
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+    Ok((command, options))
+}
 
-        let mut iter = data.iter();
+fn main() {
+    let (command, options) = match parse_args(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("huffman: {}", e);
+            eprintln!("Try 'huffman --help' for more information.");
+            process::exit(1);
+        },
+    };
+    if command == Command::Help {
+        let _ = writeln!(io::stdout(), "{}", USAGE);
+        return;
+    }
+    if command == Command::Bench {
+        if let Err(e) = run_bench(&options) {
+            eprintln!("huffman: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if matches!(command, Command::Add | Command::List | Command::Extract) {
+        if let Err(e) = run_archive(command, &options) {
+            eprintln!("huffman: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
-        let mut size: u32 = 0;
-        for i in 0..4 {
-            size |= (*iter.next().unwrap() as u32) << ((3 - i) * 8);
+    let mut failed = false;
+    for file in &options.files {
+        if let Err(e) = run(command, &options, file) {
+            eprintln!("huffman: {}: {}", file, e);
+            failed = true;
         }
-        // println!("{}", size);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
 
-        let mut header_iter = iter.clone().take(size as usize).cloned();
-        let mut table = conversion::Table::new();
-        loop {
-            let test = header_iter.next();
-            if test == None {
-                break;
-            }
-            // FIXME not working with lorem
-            let key             = test.unwrap();
-            let bits_len: usize = header_iter.next().unwrap().into();
-            let value: Vec<u8>  = header_iter.clone().take(bits_len / 8 +
-                if bits_len % 8 != 0 {1} else {0}).collect();
-            for _ in 0..(bits_len / 8 + 1) {
-                header_iter.next();
-            }
+/// Content of `file`, mapped in memory if possible.
+fn read_input(file: &str) -> io::Result<Mmap> {
+    if file == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        Ok(Mmap::from(data))
+    } else {
+        Mmap::open(file)
+    }
+}
 
-            table.0.insert(key, BitSet { data: value, len: bits_len });
+/// Path to write the output of `command` on `file` to, `None` for standard output.
+fn output_path(command: Command, options: &Options, file: &str) -> Result<Option<PathBuf>, String> {
+    if let Some(output) = &options.output {
+        return Ok(if output.as_os_str() == "-" { None } else { Some(output.clone()) });
+    }
+    if options.stdout || file == "-" || !matches!(command, Command::Compress | Command::Decompress) {
+        return Ok(None);
+    }
+    match command {
+        Command::Compress if file.ends_with(SUFFIX) && !options.force => {
+            Err(format!("already has {} suffix -- unchanged", SUFFIX))
+        },
+        Command::Compress => Ok(Some(PathBuf::from(format!("{}{}", file, SUFFIX)))),
+        _ => match [SUFFIX, gzip::SUFFIX, zlib::SUFFIX].iter().find_map(|suffix| file.strip_suffix(suffix)) {
+            Some(stem) if !stem.is_empty() => Ok(Some(PathBuf::from(stem))),
+            _ => Err("unknown suffix -- ignored".to_string()),
+        },
+    }
+}
+
+/// Metadata of `file` to store with its compressed content.
+fn file_metadata(file: &str, size: u64, options: &Options) -> Metadata {
+    let mut metadata = Metadata { size: Some(size), ..Metadata::default() };
+    if file == "-" {
+        return metadata;
+    }
+    if !options.no_name {
+        metadata.name = Path::new(file).file_name().map(|n| n.to_string_lossy().into_owned());
+        metadata.mtime = fs::metadata(file)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|t| match t.duration_since(UNIX_EPOCH) {
+                Ok(d)  => d.as_secs() as i64,
+                Err(e) => -(e.duration().as_secs() as i64),
+            });
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.mode = fs::metadata(file).ok().map(|m| m.permissions().mode() & 0o7777);
+    }
+    metadata
+}
+
+/// Set the modification time and permissions of `path` to the ones in `metadata`.
+fn restore_metadata(path: &Path, metadata: &Metadata) -> io::Result<()> {
+    if let Some(mtime) = metadata.mtime {
+        let time = if mtime >= 0 {
+            UNIX_EPOCH + Duration::from_secs(mtime as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(mtime.unsigned_abs())
+        };
+        fs::File::options().write(true).open(path)?.set_modified(time)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = metadata.mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
         }
-        // println!("{:?}", table);
+    }
+    Ok(())
+}
+
+fn run(command: Command, options: &Options, file: &str) -> Result<(), String> {
+    let mut output = output_path(command, options, file)?;
+    let check_output = |output: &Option<PathBuf>| match output {
+        Some(path) if path.exists() && !options.force => Err(format!("{} already exists", path.display())),
+        _ => Ok(()),
+    };
+    check_output(&output)?;
 
-        let data: Vec<u8> = iter.skip(size as usize).cloned().collect();
-        let mut bitset = BitSet { len: data.len() * 8, data: data };
+    if command == Command::Verify {
+        let verified = if file == "-" {
+            huffman::verify(io::stdin().lock())
+        } else {
+            fs::File::open(file).map_err(huffman::Error::from).and_then(huffman::verify)
+        };
+        let status = if verified.is_ok() { "OK" } else { "FAILED" };
+        writeln!(io::stdout().lock(), "{}: {}", file, status).map_err(|e| e.to_string())?;
+        return verified.map(|_| ()).map_err(|e| e.to_string());
+    }
 
-        // TODO: store bit size in serialized, cause wrong matches due to padding
-        let mut found = true;
-        let mut content = Vec::new();
-        while found {
-            println!("{:?}", bitset);
-            found = false;
-            for (k, v) in &table.0 {
-                if bitset.start_with(&v) {
-                    content.push(k);
-                    bitset <<= v.len;
-                    found = true;
+    if matches!(command, Command::Compress | Command::Decompress | Command::Cat) {
+        let mut restored = None;
+        if command == Command::Compress {
+            compress(options, file, &output)?;
+        } else {
+            let mapped = if file == "-" { None } else { Some(Mmap::open(file).map_err(|e| e.to_string())?) };
+            let mut input: Box<dyn Read> = match &mapped {
+                Some(mapped) => Box::new(&mapped[..]),
+                None         => Box::new(io::stdin().lock()),
+            };
+            let mut prefix = Vec::new();
+            (&mut input).take(format::PREFIX_SIZE as u64).read_to_end(&mut prefix).map_err(|e| e.to_string())?;
+            let mut input = io::Cursor::new(prefix).chain(input);
+            // gzip and zlib data is decoded in memory, huffman data a chunk at a time
+            let (metadata, decoded, header) = match Format::detect(input.get_ref().0.get_ref()) {
+                Some(Format::Huffman) => {
+                    let (metadata, header) = container::read_header_from(&mut input).map_err(|e| e.to_string())?;
+                    (metadata, Vec::new(), Some(header))
+                },
+                Some(format) => {
+                    let read;
+                    // a file is decoded from its mapping rather than copied
+                    let data = match &mapped {
+                        Some(mapped) => &mapped[..],
+                        None => {
+                            let mut data = Vec::new();
+                            input.read_to_end(&mut data).map_err(|e| e.to_string())?;
+                            read = data;
+                            &read[..]
+                        },
+                    };
+                    let (decoded, metadata) = match format {
+                        Format::Gzip => gzip::decompress(data),
+                        _            => zlib::decompress(data).map(|decoded| (decoded, Metadata::default())),
+                    }.map_err(|e| e.to_string())?;
+                    (metadata, decoded, None)
+                },
+                None if input.get_ref().0.get_ref().is_empty() => return Err(huffman::Error::UnexpectedEof.to_string()),
+                None => return Err("not in huffman, gzip or zlib format".to_string()),
+            };
+            if command == Command::Decompress && options.name && options.output.is_none() && output.is_some() {
+                if let Some(name) = &metadata.name {
+                    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                        return Err(format!("invalid stored name {:?}", name));
+                    }
+                    output = Some(Path::new(file).with_file_name(name));
+                    check_output(&output)?;
                 }
+                restored = Some(metadata.clone());
             }
+            write_output(&output, |out| match header {
+                Some(header) => {
+                    let size = container::decode_from(input, &header, out)?;
+                    container::check_size(&metadata, size)
+                },
+                None => out.write_all(&decoded).map_err(huffman::Error::from),
+            })?;
         }
-        for x in content {
-            print!("{}", *x as char);
+        if let (Some(path), Some(metadata)) = (&output, &restored) {
+            restore_metadata(path, metadata).map_err(|e| format!("{}: {}", path.display(), e))?;
         }
+        if command != Command::Cat && output.is_some() && options.output.is_none() && !options.keep {
+            fs::remove_file(file).map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    }
+
+    let data = read_input(file).map_err(|e| e.to_string())?;
+    let converted = match command {
+        Command::Stats => {
+            let stats = Stats::from_data(&data);
+            if options.json {
+                format!("{}\n", stats.to_json()).into_bytes()
+            } else {
+                stats.to_string().into_bytes()
+            }
+        },
+        Command::Tree if data.is_empty() => return Err("empty input has no tree".to_string()),
+        Command::Tree => {
+            let tree = Tree::from_data(&data);
+            match options.format.as_deref() {
+                Some("dot") => tree.to_dot().into_bytes(),
+                _           => format!("{:?}", tree).into_bytes(),
+            }
+        },
+        Command::Table => {
+            let codebook = Codebook::from_data(&data);
+            match options.format.as_deref() {
+                Some("json") => codebook.to_json().into_bytes(),
+                _            => codebook.to_csv().into_bytes(),
+            }
+        },
+        Command::Inspect => {
+            let report = inspect::inspect(&data);
+            let mut out = String::new();
+            if options.files.len() > 1 {
+                out.push_str(&format!("{}:\n", file));
+            }
+            out.push_str(&report.to_string());
+            io::stdout().lock().write_all(out.as_bytes()).map_err(|e| e.to_string())?;
+            if !report.is_ok() {
+                return Err(format!("{} problems found", report.problems.len()));
+            }
+            return Ok(());
+        },
+        _ => unreachable!(),
+    };
+
+    match &output {
+        Some(path) => fs::write(path, &converted),
+        None       => io::stdout().write_all(&converted),
+    }.map_err(|e| e.to_string())
+}
+
+/// Compress `file` to `output` from its mapping in memory,
+/// standard input being spooled to be read twice.
+fn compress(options: &Options, file: &str, output: &Option<PathBuf>) -> Result<(), String> {
+    let table = match &options.table {
+        Some(path) => Some(fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| Codebook::parse(&s).and_then(|c| c.to_table()).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => None,
+    };
+    if file == "-" {
+        let input = Spool::new(io::stdin().lock(), spool::MEMORY_LIMIT).map_err(|e| e.to_string())?;
+        let compression = container::Options {
+            table: table.as_ref(),
+            metadata: file_metadata(file, input.len().map_err(|e| e.to_string())?, options),
+            method: options.method,
+            window: options.window,
+        };
+        return write_output(output, |out| container::compress_stream(input, out, &compression));
+    }
+    let input = Mmap::open(file).map_err(|e| e.to_string())?;
+    let compression = container::Options {
+        table: table.as_ref(),
+        metadata: file_metadata(file, input.len() as u64, options),
+        method: options.method,
+        window: options.window,
+    };
+    write_output(output, |out| container::compress_stream(io::Cursor::new(&input[..]), out, &compression))
+}
+
+/// Run `write` on `output`, or standard output if `None`, removing the output file if it fails.
+fn write_output<F>(output: &Option<PathBuf>, write: F) -> Result<(), String>
+where
+    F: FnOnce(&mut dyn Write) -> huffman::Result<()>,
+{
+    let result = match output {
+        Some(path) => fs::File::create(path).map_err(huffman::Error::from).and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()?;
+            Ok(())
+        }),
+        None => {
+            let mut out = io::BufWriter::new(io::stdout().lock());
+            write(&mut out).and_then(|_| Ok(out.flush()?))
+        },
+    };
+    if let (Err(_), Some(path)) = (&result, output) {
+        let _ = fs::remove_file(path);
+    }
+    result.map_err(|e| e.to_string())
+}
+
+/// Benchmark the files, or the built-in corpora, printing one CSV line per input as it is done
+/// or a JSON array once they all are.
+fn run_bench(options: &Options) -> Result<(), String> {
+    let iterations = options.iterations.unwrap_or(5);
+    let inputs: Vec<(String, Mmap)> = if options.files.is_empty() {
+        bench::corpora().into_iter().map(|(name, data)| (name.to_string(), Mmap::from(data))).collect()
+    } else {
+        options.files
+            .iter()
+            .map(|file| read_input(file).map(|data| (file.clone(), data)).map_err(|e| format!("{}: {}", file, e)))
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut out = io::stdout().lock();
+    let print = |out: &mut io::StdoutLock, line: &str| writeln!(out, "{}", line).map_err(|e| e.to_string());
+    if options.json {
+        // all inputs are run first, so that a failure does not leave the array unterminated
+        let benches: Vec<String> = inputs
+            .iter()
+            .map(|(name, data)| {
+                Bench::run(name, data, iterations).map(|b| b.to_json()).map_err(|e| format!("{}: {}", name, e))
+            })
+            .collect::<Result<_, _>>()?;
+        return print(&mut out, &format!("[\n  {}\n]", benches.join(",\n  ")));
+    }
+    print(&mut out, Bench::CSV_HEADER)?;
+    for (name, data) in &inputs {
+        let bench = Bench::run(name, data, iterations).map_err(|e| format!("{}: {}", name, e))?;
+        print(&mut out, &bench.to_string())?;
+    }
+    Ok(())
+}
 
+/// Files under `path`, in a stable order.
+fn walk(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        let mut children: Vec<PathBuf> = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        children.sort();
+        for child in children {
+            walk(&child, files)?;
+        }
     } else {
-        // let f = fs::File::open(file_path).unwrap();
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Path as stored in an archive: relative, normalized and separated by slashes.
+fn archive_path(path: &Path) -> String {
+    let mut components: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(s) => components.push(s.to_string_lossy().into_owned()),
+            Component::ParentDir => { components.pop(); },
+            _                    => {},
+        }
+    }
+    components.join("/")
+}
 
-        let data: Vec<u8> = io::stdin().bytes().map(|x| x.unwrap()).collect();
+fn run_archive(command: Command, options: &Options) -> Result<(), String> {
+    let archive_file = &options.files[0];
+    let members = &options.files[1..];
+    let with_file = |e: huffman::Error| format!("{}: {}", archive_file, e);
 
-        let tree = Tree::from_data(&data);
-        // print!("{:?}", tree);
+    if command == Command::Add {
+        let mut files = Vec::new();
+        for member in members {
+            walk(Path::new(member), &mut files).map_err(|e| format!("{}: {}", member, e))?;
+        }
+        // the archive itself, when it is in a directory being added
+        if let Ok(archive) = fs::canonicalize(archive_file) {
+            files.retain(|file| fs::canonicalize(file).map_or(true, |file| file != archive));
+        }
+        let contents = files
+            .iter()
+            .map(|file| Mmap::open(file).map_err(|e| format!("{}: {}", file.display(), e)))
+            .collect::<Result<Vec<Mmap>, String>>()?;
 
-        let table = conversion::Table::from_tree(&tree);
-        // print!("{:?}", table);
+        let mut writer = if Path::new(archive_file).exists() {
+            let data = fs::read(archive_file).map_err(|e| format!("{}: {}", archive_file, e))?;
+            let writer = archive::Writer::append(data).map_err(with_file)?;
+            if options.solid && !writer.is_solid() {
+                return Err(format!("{}: cannot add to an existing archive in solid mode", archive_file));
+            }
+            writer
+        } else if options.solid {
+            let slices: Vec<&[u8]> = contents.iter().map(|c| &c[..]).collect();
+            archive::Writer::solid(archive::solid_table(&slices))
+        } else {
+            archive::Writer::new()
+        };
+        for (file, data) in files.iter().zip(contents.iter()) {
+            writer.add(&archive_path(file), data).map_err(|e| format!("{}: {}", file.display(), e))?;
+        }
+        return fs::write(archive_file, writer.finish()).map_err(|e| format!("{}: {}", archive_file, e));
+    }
 
-        let converted_data = table.convert(data);
-        // println!("{:?}", converted_data);
+    let data = read_input(archive_file).map_err(|e| format!("{}: {}", archive_file, e))?;
+    let entries = archive::read(&data).map_err(with_file)?;
+    if let Some(missing) = members.iter().find(|m| !entries.iter().any(|e| &e.path == *m)) {
+        return Err(format!("{}: {}: not found in archive", archive_file, missing));
+    }
+    let selected = entries.iter().filter(|e| members.is_empty() || members.contains(&e.path));
 
-        let header = table.serialize();
+    if command == Command::List {
+        let mut out = String::new();
+        for entry in selected {
+            out.push_str(&format!("{:12} {:12}  {}\n", entry.size, entry.compressed_size(), entry.path));
+        }
+        return io::stdout().write_all(out.as_bytes()).map_err(|e| e.to_string());
+    }
 
-        io::stdout().write_all(&header).unwrap();
-        io::stdout().write_all(&converted_data).unwrap();
+    let directory = options.output.clone().unwrap_or_else(|| PathBuf::from("."));
+    for entry in selected {
+        let path = directory.join(&entry.path);
+        if path.exists() && !options.force {
+            return Err(format!("{} already exists", path.display()));
+        }
+        let content = entry.decompress().map_err(|e| format!("{}: {}: {}", archive_file, entry.path, e))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e))?;
     }
+    Ok(())
 }
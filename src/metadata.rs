@@ -0,0 +1,162 @@
+//! Optional information on the original file stored in the header of a compressed file,
+//! similar to the FNAME and MTIME fields of gzip.
+
+use std::io::Read;
+
+use super::container::read_exact;
+use super::error::{Error, Result};
+
+const FLAG_NAME: u8  = 1 << 0;
+const FLAG_MTIME: u8 = 1 << 1;
+const FLAG_MODE: u8  = 1 << 2;
+const FLAG_SIZE: u8  = 1 << 3;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// File name, without its directory.
+    pub name: Option<String>,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: Option<i64>,
+    /// Unix permission bits.
+    pub mode: Option<u32>,
+    /// Size of the original content.
+    pub size: Option<u64>,
+}
+
+impl Metadata {
+    /// Flags byte followed by the fields that are present.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut flags = 0;
+        let mut out = vec![0];
+        if let Some(name) = &self.name {
+            flags |= FLAG_NAME;
+            let name = &name.as_bytes()[..name.len().min(u16::MAX as usize)];
+            out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            out.extend_from_slice(name);
+        }
+        if let Some(mtime) = self.mtime {
+            flags |= FLAG_MTIME;
+            out.extend_from_slice(&mtime.to_be_bytes());
+        }
+        if let Some(mode) = self.mode {
+            flags |= FLAG_MODE;
+            out.extend_from_slice(&mode.to_be_bytes());
+        }
+        if let Some(size) = self.size {
+            flags |= FLAG_SIZE;
+            out.extend_from_slice(&size.to_be_bytes());
+        }
+        out[0] = flags;
+        out
+    }
+
+    /// Parse metadata written by `serialize` at the start of `data`,
+    /// returns it with the number of bytes read.
+    pub fn deserialize(data: &[u8]) -> Result<(Metadata, usize)> {
+        let flags = *data.first().ok_or(Error::UnexpectedEof)?;
+        if flags & !(FLAG_NAME | FLAG_MTIME | FLAG_MODE | FLAG_SIZE) != 0 {
+            return Err(Error::InvalidHeader(format!("unknown flags {:#04x}", flags)));
+        }
+        let mut i = 1;
+        let mut field = |size: usize| -> Result<&[u8]> {
+            let bytes = data.get(i..i + size).ok_or(Error::UnexpectedEof)?;
+            i += size;
+            Ok(bytes)
+        };
+        let mut metadata = Metadata::default();
+        if flags & FLAG_NAME != 0 {
+            let len = field(2)?;
+            let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+            let name = String::from_utf8(field(len)?.to_vec())
+                .map_err(|_| Error::InvalidHeader("file name is not valid UTF-8".to_string()))?;
+            metadata.name = Some(name);
+        }
+        if flags & FLAG_MTIME != 0 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(field(8)?);
+            metadata.mtime = Some(i64::from_be_bytes(bytes));
+        }
+        if flags & FLAG_MODE != 0 {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(field(4)?);
+            metadata.mode = Some(u32::from_be_bytes(bytes));
+        }
+        if flags & FLAG_SIZE != 0 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(field(8)?);
+            metadata.size = Some(u64::from_be_bytes(bytes));
+        }
+        Ok((metadata, i))
+    }
+
+    /// Read metadata written by `serialize` from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Metadata> {
+        let mut data = vec![0];
+        read_exact(reader, &mut data)?;
+        let flags = data[0];
+        if flags & FLAG_NAME != 0 {
+            let mut len = [0; 2];
+            read_exact(reader, &mut len)?;
+            data.extend_from_slice(&len);
+            data.resize(data.len() + u16::from_be_bytes(len) as usize, 0);
+        }
+        let fixed_size = [(FLAG_MTIME, 8), (FLAG_MODE, 4), (FLAG_SIZE, 8)]
+            .iter()
+            .filter(|(flag, _)| flags & flag != 0)
+            .map(|(_, size)| size)
+            .sum::<usize>();
+        let start = if flags & FLAG_NAME != 0 { 3 } else { 1 };
+        data.resize(data.len() + fixed_size, 0);
+        read_exact(reader, &mut data[start..])?;
+        Metadata::deserialize(&data).map(|(metadata, _)| metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let metadata = Metadata::default();
+        assert_eq!(metadata.serialize(), vec![0]);
+        assert_eq!(Metadata::deserialize(&[0]).unwrap(), (metadata, 1));
+    }
+
+    #[test]
+    fn round_trip() {
+        let metadata = Metadata {
+            name: Some("report.csv".to_string()),
+            mtime: Some(1_600_000_000),
+            mode: Some(0o644),
+            size: Some(1 << 40),
+        };
+        let mut bytes = metadata.serialize();
+        let len = bytes.len();
+        bytes.extend(b"rest");
+        assert_eq!(Metadata::deserialize(&bytes).unwrap(), (metadata, len));
+    }
+
+    #[test]
+    fn read_from() {
+        let metadata = Metadata {
+            name: Some("a".to_string()),
+            mtime: None,
+            mode: Some(0o755),
+            size: Some(3),
+        };
+        let mut bytes = metadata.serialize();
+        bytes.extend(b"rest");
+        let mut reader = &bytes[..];
+        assert_eq!(Metadata::read_from(&mut reader).unwrap(), metadata);
+        assert_eq!(reader, b"rest");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Metadata::deserialize(&[]).is_err());
+        assert!(Metadata::deserialize(&[0x80]).is_err());
+        assert!(Metadata::deserialize(&[FLAG_NAME, 0, 4, b'a']).is_err());
+        assert!(Metadata::deserialize(&[FLAG_MTIME, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Read-only memory mapping of a file, to use its content without copying it.
+//!
+//! Files are mapped with `mmap` on 64-bit Unix systems. Elsewhere, or when mapping
+//! fails (empty files, special files), the content is read into memory instead.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::os::raw::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+enum Inner {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    Mapped(*const u8, usize),
+    Owned(Vec<u8>),
+}
+
+/// Content of a file, mapped in memory when possible.
+///
+/// The file should not be modified while it is mapped, the changes may or may not be seen.
+pub struct Mmap(Inner);
+
+// The mapping is private and read-only.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Mmap> {
+        Mmap::map(&File::open(path)?)
+    }
+
+    pub fn map(file: &File) -> io::Result<Mmap> {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        {
+            use std::os::unix::io::AsRawFd;
+            let len = file.metadata()?.len() as usize;
+            if len > 0 {
+                let ptr = unsafe {
+                    sys::mmap(std::ptr::null_mut(), len, sys::PROT_READ, sys::MAP_PRIVATE, file.as_raw_fd(), 0)
+                };
+                if ptr as isize != -1 {
+                    return Ok(Mmap(Inner::Mapped(ptr as *const u8, len)));
+                }
+            }
+        }
+        let mut data = Vec::new();
+        (&*file).read_to_end(&mut data)?;
+        Ok(Mmap(Inner::Owned(data)))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        !matches!(self.0, Inner::Owned(_))
+    }
+}
+
+impl From<Vec<u8>> for Mmap {
+    fn from(data: Vec<u8>) -> Mmap {
+        Mmap(Inner::Owned(data))
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            #[cfg(all(unix, target_pointer_width = "64"))]
+            Inner::Mapped(ptr, len) => unsafe { std::slice::from_raw_parts(*ptr, *len) },
+            Inner::Owned(data)      => data,
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        if let Inner::Mapped(ptr, len) = self.0 {
+            unsafe {
+                sys::munmap(ptr as *mut _, len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn map_file() {
+        let path = std::env::temp_dir().join(format!("huffman-mmap-{}", std::process::id()));
+        fs::write(&path, b"abracadabra").unwrap();
+        let map = Mmap::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&map[..], b"abracadabra");
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        assert!(map.is_mapped());
+    }
+
+    #[test]
+    fn empty_file() {
+        let path = std::env::temp_dir().join(format!("huffman-mmap-empty-{}", std::process::id()));
+        fs::write(&path, b"").unwrap();
+        let map = Mmap::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(map.is_empty());
+        assert!(!map.is_mapped());
+    }
+
+    #[test]
+    fn owned() {
+        let map = Mmap::from(b"abc".to_vec());
+        assert_eq!(&map[..], b"abc");
+        assert!(!map.is_mapped());
+    }
+}
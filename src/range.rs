@@ -0,0 +1,230 @@
+//! Range coding of the bytes, with the counts the Huffman tree is built from scaled to 16 bits.
+//!
+//! Unlike a Huffman code, a range coder is not limited to a whole number of bits per symbol,
+//! which is smaller when some bytes are much more frequent than the others.
+//!
+//! ```text
+//! header of the frequencies, out of 2^16, as described in `frequencies`
+//! range coded data, starting with a 0 byte
+//! ```
+//!
+//! The coder is the one of LZMA: a 32-bit range and a 33-bit low bound whose carry
+//! is propagated to the bytes already computed.
+
+use std::io::Write;
+
+use super::conversion::DECODE_BUFFER_SIZE;
+use super::error::{Error, Result};
+use super::frequencies;
+
+/// Frequencies add up to at most `1 << FREQ_BITS`.
+pub const FREQ_BITS: u32 = 16;
+
+const TOTAL: u32 = 1 << FREQ_BITS;
+
+/// The range is shifted by a byte whenever it is below this.
+const TOP: u32 = 1 << 24;
+
+/// Start of the interval of each byte, after those of the smaller bytes.
+fn cumulative(freqs: &[u32; 256]) -> [u32; 256] {
+    let mut cum = [0; 256];
+    for b in 1..256 {
+        cum[b] = cum[b - 1] + freqs[b - 1];
+    }
+    cum
+}
+
+struct Encoder {
+    low: u64,
+    range: u32,
+    /// Byte not written yet, as a carry may still increment it.
+    cache: u8,
+    /// Number of bytes not written yet: `cache` followed by 0xff bytes.
+    cache_size: u64,
+    out: Vec<u8>,
+}
+
+impl Encoder {
+    fn new(out: Vec<u8>) -> Encoder {
+        Encoder { low: 0, range: u32::MAX, cache: 0, cache_size: 1, out }
+    }
+
+    fn encode(&mut self, cum: u32, freq: u32) {
+        let r = self.range >> FREQ_BITS;
+        self.low += r as u64 * cum as u64;
+        self.range = r * freq;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            while self.cache_size > 0 {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+struct Decoder<'a> {
+    code: u32,
+    range: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8]) -> Result<Decoder<'a>> {
+        let start = data.get(..5).ok_or(Error::UnexpectedEof)?;
+        if start[0] != 0 {
+            return Err(Error::InvalidPayload("range coded data does not start with a 0 byte".to_string()));
+        }
+        let code = u32::from_be_bytes([start[1], start[2], start[3], start[4]]);
+        Ok(Decoder { code, range: u32::MAX, data: &data[5..] })
+    }
+
+    /// Read the byte whose interval holds the code, `symbols` giving the byte of each frequency slot.
+    fn decode(&mut self, freqs: &[u32; 256], cum: &[u32; 256], symbols: &[u8]) -> Result<u8> {
+        let r = self.range >> FREQ_BITS;
+        let slot = self.code / r;
+        if slot >= symbols.len() as u32 {
+            return Err(Error::InvalidPayload("range coded data is outside of the frequencies".to_string()));
+        }
+        let byte = symbols[slot as usize];
+        self.code -= r * cum[byte as usize];
+        self.range = r * freqs[byte as usize];
+        while self.range < TOP {
+            let (next, rest) = self.data.split_first().ok_or(Error::UnexpectedEof)?;
+            self.code = self.code << 8 | *next as u32;
+            self.range <<= 8;
+            self.data = rest;
+        }
+        Ok(byte)
+    }
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let freqs = frequencies::normalize(data, TOTAL);
+    let cum = cumulative(&freqs);
+    let mut encoder = Encoder::new(frequencies::header(data.len() as u64, &freqs));
+    for byte in data {
+        encoder.encode(cum[*byte as usize], freqs[*byte as usize]);
+    }
+    encoder.finish()
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decode_to(data, &mut out)?;
+    Ok(out)
+}
+
+/// Decode `data` into `out` a buffer at a time, so that the size in the header,
+/// which a few bytes of range coded data can reach, does not decide what is allocated.
+pub fn decode_to<W: Write>(data: &[u8], out: &mut W) -> Result<()> {
+    let (size, freqs, payload) = frequencies::read_header(data, TOTAL)?;
+    let cum = cumulative(&freqs);
+    let symbols: Vec<u8> = (0..=255u8)
+        .flat_map(|b| std::iter::repeat_n(b, freqs[b as usize] as usize))
+        .collect();
+    let mut decoder = Decoder::new(payload)?;
+    let mut buffer = Vec::with_capacity(DECODE_BUFFER_SIZE);
+    for _ in 0..size {
+        buffer.push(decoder.decode(&freqs, &cum, &symbols)?);
+        if buffer.len() == DECODE_BUFFER_SIZE {
+            out.write_all(&buffer)?;
+            buffer.clear();
+        }
+    }
+    if !decoder.data.is_empty() {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    out.write_all(&buffer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bench;
+    use super::super::conversion::Table;
+    use super::super::tree::Tree;
+
+    #[test]
+    fn round_trips() {
+        for data in [&b""[..], b"a", b"abracadabra", &[0; 10_000]] {
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+        // the skewed corpus gives many 0xff bytes in a row in the output, which a carry has to go through
+        for (_, corpus) in bench::corpora() {
+            let data = &corpus[..1 << 16];
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn smaller_than_huffman_on_skewed_data() {
+        // 'a' has a probability of 0.95, which a Huffman code still gives a whole bit
+        let data: Vec<u8> = (0..100_000).map(|i| if i % 20 == 0 { b'b' } else { b'a' }).collect();
+        let huffman_bits = Table::from_tree(&Tree::from_data(&data)).convert(&data).len;
+        assert_eq!(huffman_bits, data.len());
+        assert!(encode(&data).len() * 8 < huffman_bits / 3);
+    }
+
+    #[test]
+    fn errors() {
+        let encoded = encode(b"abracadabra");
+        assert!(matches!(decode(&encoded[..encoded.len() - 1]), Err(Error::UnexpectedEof)));
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+        assert!(decode(&[]).is_err());
+        // first range coded byte
+        let mut start = encoded.clone();
+        start[10 + 3 * 5] = 1;
+        assert!(decode(&start).is_err());
+        // frequencies adding up to more than 2^16
+        let mut freqs = encoded.clone();
+        freqs[11] = 0xff;
+        assert!(matches!(decode(&freqs), Err(Error::InvalidTable(_))));
+    }
+
+    /// Writer of the size of the largest write, dropping what is written.
+    struct LargestWrite(usize);
+
+    impl Write for LargestWrite {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 = self.0.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn large_size_is_streamed() {
+        // a byte of frequency 2^16 - 1 takes hundreds of thousands of bytes to use up a byte of input
+        let mut data = u64::MAX.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0, 2, b'a', 0xff, 0xff, b'b', 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        let mut out = LargestWrite(0);
+        assert!(matches!(decode_to(&data, &mut out), Err(Error::UnexpectedEof)));
+        assert_eq!(out.0, DECODE_BUFFER_SIZE);
+    }
+}
@@ -0,0 +1,120 @@
+//! Run-length encoding followed by Huffman coding, for data made of long runs of the same byte.
+//!
+//! The data is split in runs of up to 256 bytes, each run being coded as its byte
+//! followed by its length minus one, with one table for the bytes and one for the lengths:
+//!
+//! ```text
+//! table of the bytes, table of the lengths (as in a compressed file)
+//! 8 byte unsigned int: number of runs
+//! codes of the byte and length of each run, padded with zeroes to a 8-bit boundary
+//! ```
+
+use super::bits::{BitReader, BitWriter};
+use super::conversion::{Table, Trie};
+use super::error::{Error, Result};
+
+/// Longest run, so that its length minus one fits in a byte.
+pub const MAX_RUN: usize = 256;
+
+/// Runs of `data` as (byte, length) pairs, runs longer than `MAX_RUN` being split.
+pub fn runs(data: &[u8]) -> Vec<(u8, usize)> {
+    let mut runs: Vec<(u8, usize)> = Vec::new();
+    for byte in data {
+        match runs.last_mut() {
+            Some((b, len)) if b == byte && *len < MAX_RUN => *len += 1,
+            _ => runs.push((*byte, 1)),
+        }
+    }
+    runs
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let runs = runs(data);
+    let bytes = Table::from_symbols(runs.iter().map(|(b, _)| *b));
+    let lengths = Table::from_symbols(runs.iter().map(|(_, len)| (len - 1) as u8));
+
+    let mut out = bytes.serialize();
+    out.extend(lengths.serialize());
+    out.extend_from_slice(&(runs.len() as u64).to_be_bytes());
+    let mut writer = BitWriter::new(out);
+    for (byte, len) in &runs {
+        // writing to a vector cannot fail
+        writer.write_bitset(&bytes.0[byte]).unwrap();
+        writer.write_bitset(&lengths.0[&((len - 1) as u8)]).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let (bytes, bytes_size) = Table::deserialize(data)?;
+    let (lengths, lengths_size) = Table::<u8>::deserialize(&data[bytes_size..])?;
+    let data = &data[bytes_size + lengths_size..];
+    if data.len() < 8 {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut count = [0; 8];
+    count.copy_from_slice(&data[..8]);
+    let count = u64::from_be_bytes(count);
+
+    let (bytes, lengths) = (Trie::new(&bytes)?, Trie::new(&lengths)?);
+    let mut reader = BitReader::new(&data[8..]);
+    let mut out = Vec::new();
+    for _ in 0..count {
+        let byte = bytes.read_symbol(&mut reader)?;
+        let len = lengths.read_symbol(&mut reader)? as usize + 1;
+        out.resize(out.len() + len, byte);
+    }
+    if reader.remaining_in_byte() != 0 {
+        return Err(Error::InvalidPayload("padding bits are not zero".to_string()));
+    }
+    if reader.has_more_bytes()? {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        assert_eq!(decode(&encode(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn split_runs() {
+        assert_eq!(runs(b"aaabcc"), vec![(b'a', 3), (b'b', 1), (b'c', 2)]);
+        assert_eq!(runs(&[0; 600]), vec![(0, 256), (0, 256), (0, 88)]);
+        assert!(runs(b"").is_empty());
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"");
+        round_trip(b"a");
+        round_trip(b"abracadabra");
+        let mut sparse = vec![0u8; 10000];
+        for i in (0..sparse.len()).step_by(997) {
+            sparse[i] = i as u8;
+        }
+        round_trip(&sparse);
+    }
+
+    #[test]
+    fn smaller_on_runs() {
+        let mut data = vec![0u8; 4096];
+        data.extend(vec![0xff; 4096]);
+        data.extend(b"header");
+        assert!(encode(&data).len() < 64);
+    }
+
+    #[test]
+    fn errors() {
+        let encoded = encode(b"aaaabbbbbbccd");
+        assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+        assert!(decode(&[]).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Shannon–Fano coding: codes built top-down by splitting the symbols, sorted by count,
+//! in two groups of counts as close as possible, the first getting a 0 bit and the second a 1 bit.
+//!
+//! Unlike the Huffman coding, which builds its tree bottom-up, the codes are not always optimal,
+//! but they are prefix-free and can be used anywhere a `Table` is.
+
+use std::collections::HashMap;
+
+use super::bits::BitSet;
+use super::conversion::Table;
+use super::tree;
+
+pub fn from_data(data: &[u8]) -> Table {
+    from_counts(&tree::count(data))
+}
+
+pub fn from_counts(counter: &HashMap<u8, usize>) -> Table {
+    let mut symbols: Vec<(usize, u8)> = counter.iter().map(|(k, v)| (*v, *k)).collect();
+    // most frequent first, by byte for the same count so that the codes do not depend on the map's order
+    symbols.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    let mut table = Table::new();
+    match symbols.len() {
+        0 => {},
+        // a single symbol still needs a bit, as with `Table::from_tree`
+        1 => {
+            let mut bits = BitSet::new();
+            bits.push_bit(0);
+            table.0.insert(symbols[0].1, bits);
+        },
+        _ => split(&symbols, BitSet::new(), &mut table),
+    }
+    table
+}
+
+/// Give the codes of `symbols`, all starting with `prefix`.
+fn split(symbols: &[(usize, u8)], prefix: BitSet, table: &mut Table) {
+    if symbols.len() == 1 {
+        table.0.insert(symbols[0].1, prefix);
+        return;
+    }
+    let total: usize = symbols.iter().map(|(c, _)| c).sum();
+    // first split point where the first group holds at least half of the total,
+    // or the one before it if that is closer
+    let mut sum = 0;
+    let mut at = 1;
+    for (i, (count, _)) in symbols[..symbols.len() - 1].iter().enumerate() {
+        sum += count;
+        at = i + 1;
+        if 2 * sum >= total {
+            if i > 0 && 2 * sum - total > total - 2 * (sum - count) {
+                at = i;
+            }
+            break;
+        }
+    }
+    let (mut left, mut right) = (prefix.clone(), prefix);
+    left.push_bit(0);
+    right.push_bit(1);
+    split(&symbols[..at], left, table);
+    split(&symbols[at..], right, table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tree::Tree;
+
+    fn cost(table: &Table, counter: &HashMap<u8, usize>) -> usize {
+        counter.iter().map(|(k, v)| v * table.0[k].len).sum()
+    }
+
+    #[test]
+    fn textbook_example() {
+        // counts 15, 7, 6, 6, 5 for A to E: {A, B} and {C, D, E}, then {D, E}
+        let counter: HashMap<u8, usize> = [(b'A', 15), (b'B', 7), (b'C', 6), (b'D', 6), (b'E', 5)]
+            .iter()
+            .cloned()
+            .collect();
+        let table = from_counts(&counter);
+        let codes: Vec<String> = b"ABCDE".iter().map(|b| table.0[b].to_string()).collect();
+        assert_eq!(codes, ["00", "01", "10", "110", "111"]);
+        table.validate().unwrap();
+        // 89 bits where the Huffman coding takes 87
+        assert_eq!(cost(&table, &counter), 89);
+        assert_eq!(cost(&Table::from_tree(&Tree::from_counts(&counter)), &counter), 87);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"abracadabra alakazam";
+        let table = from_data(data);
+        table.validate().unwrap();
+        let bits = table.convert(data);
+        assert_eq!(table.decode(&bits.data, bits.len).unwrap(), data);
+        assert!(cost(&table, &tree::count(data)) >= cost(&Table::from_tree(&Tree::from_data(data)), &tree::count(data)));
+    }
+
+    #[test]
+    fn few_symbols() {
+        assert!(from_data(b"").0.is_empty());
+        assert_eq!(from_data(b"aaa").0[&b'a'].to_string(), "0");
+        let table = from_data(b"aab");
+        assert_eq!((table.0[&b'a'].to_string(), table.0[&b'b'].to_string()), ("0".to_string(), "1".to_string()));
+    }
+}
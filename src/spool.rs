@@ -0,0 +1,150 @@
+//! Copy of a non-seekable input, such as standard input, that can be read several times.
+//!
+//! Small inputs are kept in memory, larger ones are written to a temporary file
+//! so that compressing them takes a constant amount of memory.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Size above which the input is written to a temporary file.
+pub const MEMORY_LIMIT: usize = 16 << 20;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub enum Spool {
+    Memory(Cursor<Vec<u8>>),
+    /// Temporary file with its path, removed when dropped.
+    File(File, PathBuf),
+}
+
+impl Spool {
+    /// Read all of `reader`, in memory if it is no larger than `limit` bytes.
+    pub fn new<R: Read>(mut reader: R, limit: usize) -> io::Result<Spool> {
+        let mut data = Vec::new();
+        (&mut reader).take(limit as u64 + 1).read_to_end(&mut data)?;
+        if data.len() <= limit {
+            return Ok(Spool::Memory(Cursor::new(data)));
+        }
+
+        let path = temp_path();
+        let mut options = File::options();
+        options.read(true).write(true).create_new(true);
+        // Only readable by the user, as it holds the data being compressed.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(&path)?;
+        // Built before writing to it, so that the file is removed if that fails.
+        let mut spool = Spool::File(file, path);
+        if let Spool::File(file, _) = &mut spool {
+            let mut out = io::BufWriter::new(file);
+            out.write_all(&data)?;
+            drop(data);
+            io::copy(&mut reader, &mut out)?;
+            out.into_inner().map_err(|e| e.into_error())?.seek(SeekFrom::Start(0))?;
+        }
+        Ok(spool)
+    }
+
+    pub fn len(&self) -> io::Result<u64> {
+        match self {
+            Spool::Memory(cursor) => Ok(cursor.get_ref().len() as u64),
+            Spool::File(file, _)  => Ok(file.metadata()?.len()),
+        }
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    pub fn is_in_memory(&self) -> bool {
+        matches!(self, Spool::Memory(_))
+    }
+}
+
+fn temp_path() -> PathBuf {
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    env::temp_dir().join(format!("huffman-spool-{}-{}", process::id(), n))
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Spool::Memory(cursor) => cursor.read(buf),
+            Spool::File(file, _)  => file.read(buf),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Spool::Memory(cursor) => cursor.seek(pos),
+            Spool::File(file, _)  => file.seek(pos),
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Spool::File(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_twice(spool: &mut Spool) -> (Vec<u8>, Vec<u8>) {
+        let mut first = Vec::new();
+        spool.read_to_end(&mut first).unwrap();
+        spool.seek(SeekFrom::Start(0)).unwrap();
+        let mut second = Vec::new();
+        spool.read_to_end(&mut second).unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn small_in_memory() {
+        let mut spool = Spool::new(&b"abracadabra"[..], 16).unwrap();
+        assert!(spool.is_in_memory());
+        assert_eq!(spool.len().unwrap(), 11);
+        assert_eq!(read_twice(&mut spool), (b"abracadabra".to_vec(), b"abracadabra".to_vec()));
+    }
+
+    #[test]
+    fn large_in_file() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut spool = Spool::new(&data[..], 100).unwrap();
+        assert!(!spool.is_in_memory());
+        assert_eq!(spool.len().unwrap(), 1000);
+        assert_eq!(read_twice(&mut spool), (data.clone(), data));
+
+        let path = match &spool {
+            Spool::File(_, path) => path.clone(),
+            _                    => unreachable!(),
+        };
+        assert!(path.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        drop(spool);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn limit_is_inclusive() {
+        assert!(Spool::new(&[0u8; 16][..], 16).unwrap().is_in_memory());
+        assert!(!Spool::new(&[0u8; 17][..], 16).unwrap().is_in_memory());
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+
+use super::bits::BitSet;
+use super::container;
+use super::conversion::Table;
+use super::json;
+use super::shannon_fano;
+use super::tree::{self, Tree};
+use super::tunstall::Tunstall;
+
+/// Size of the codewords of the Tunstall code the Huffman coding is compared to.
+pub const TUNSTALL_BITS: u32 = 12;
+
+pub struct SymbolStats {
+    pub symbol: u8,
+    pub count: usize,
+    pub probability: f64,
+    pub code: BitSet,
+}
+
+/// How another code of the same data performs.
+pub struct Comparison {
+    pub name: String,
+    pub average_code_length: f64,
+    /// `None` when the entropy is 0, as in `Stats`.
+    pub efficiency: Option<f64>,
+}
+
+impl Comparison {
+    fn new(name: &str, average_code_length: f64, entropy: f64) -> Comparison {
+        Comparison {
+            name: name.to_string(),
+            average_code_length,
+            efficiency: efficiency(entropy, average_code_length),
+        }
+    }
+}
+
+/// Entropy over the average code length, not defined when the entropy is 0
+/// as there is then nothing to code.
+fn efficiency(entropy: f64, average_code_length: f64) -> Option<f64> {
+    if entropy == 0.0 { None } else { Some(entropy / average_code_length) }
+}
+
+/// Efficiency as a percentage, "n/a" when it is not defined.
+fn percent(efficiency: Option<f64>) -> String {
+    efficiency.map_or_else(|| "n/a".to_string(), |e| format!("{:.2}%", e * 100.0))
+}
+
+/// Efficiency as a JSON number, `null` when it is not defined.
+fn json_efficiency(efficiency: Option<f64>) -> String {
+    efficiency.map_or_else(|| "null".to_string(), |e| e.to_string())
+}
+
+/// Report on how well the Huffman coding of some data performs.
+///
+/// Sizes are in bytes, entropy and code lengths are in bits per symbol.
+pub struct Stats {
+    pub symbols: Vec<SymbolStats>,
+    pub total: usize,
+    pub entropy: f64,
+    pub average_code_length: f64,
+    pub redundancy: f64,
+    /// `None` when the entropy is 0, that is for data of at most one distinct byte.
+    pub efficiency: Option<f64>,
+    pub header_size: usize,
+    pub compressed_size: usize,
+    /// Shannon–Fano and Tunstall codes of the data.
+    /// The Tunstall dictionary of a single byte is that byte alone, so it takes a whole codeword.
+    pub comparisons: Vec<Comparison>,
+}
+
+impl Stats {
+    pub fn from_data(data: &[u8]) -> Stats {
+        let counter = tree::count(data);
+        if counter.is_empty() {
+            return Stats {
+                symbols: Vec::new(),
+                total: 0,
+                entropy: 0.0,
+                average_code_length: 0.0,
+                redundancy: 0.0,
+                efficiency: None,
+                header_size: container::header_size(&Table::new()),
+                compressed_size: container::header_size(&Table::new()),
+                comparisons: Vec::new(),
+            };
+        }
+        let table = Table::from_tree(&Tree::from_counts(&counter));
+
+        let total = data.len();
+        let mut symbols: Vec<SymbolStats> = counter
+            .iter()
+            .map(|(k, v)| SymbolStats {
+                symbol: *k,
+                count: *v,
+                probability: *v as f64 / total as f64,
+                code: table.0[k].clone(),
+            })
+            .collect();
+        symbols.sort_by_key(|s| s.symbol);
+
+        let entropy: f64 = symbols
+            .iter()
+            .map(|s| s.probability * (1.0 / s.probability).log2())
+            .sum();
+        let average_code_length: f64 = symbols
+            .iter()
+            .map(|s| s.probability * s.code.len as f64)
+            .sum();
+        let bits: usize = symbols.iter().map(|s| s.count * s.code.len).sum();
+        let header_size = container::header_size(&table);
+
+        let shannon_fano = shannon_fano::from_counts(&counter);
+        let shannon_fano_length: f64 = symbols
+            .iter()
+            .map(|s| s.probability * shannon_fano.0[&s.symbol].len as f64)
+            .sum();
+        let tunstall = Tunstall::from_counts(&counter, TUNSTALL_BITS);
+        let tunstall_length = TUNSTALL_BITS as f64 / tunstall.average_word_length(&counter);
+        let comparisons = vec![
+            Comparison::new("shannon-fano", shannon_fano_length, entropy),
+            Comparison::new(&format!("tunstall ({} bits)", TUNSTALL_BITS), tunstall_length, entropy),
+        ];
+
+        Stats {
+            symbols,
+            total,
+            entropy,
+            average_code_length,
+            redundancy: average_code_length - entropy,
+            efficiency: efficiency(entropy, average_code_length),
+            header_size,
+            compressed_size: header_size + bits.div_ceil(8),
+            comparisons,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let symbols: Vec<String> = self.symbols
+            .iter()
+            .map(|s| format!(
+                "{{\"symbol\":{},\"count\":{},\"probability\":{},\"code_length\":{},\"code\":\"{}\"}}",
+                s.symbol, s.count, s.probability, s.code.len, s.code
+            ))
+            .collect();
+        let comparisons: Vec<String> = self.comparisons
+            .iter()
+            .map(|c| format!(
+                "{{\"name\":{},\"average_code_length\":{},\"efficiency\":{}}}",
+                json::escape(&c.name), c.average_code_length, json_efficiency(c.efficiency)
+            ))
+            .collect();
+        format!(
+            "{{\"total\":{},\"entropy\":{},\"average_code_length\":{},\"redundancy\":{},\
+\"efficiency\":{},\"header_size\":{},\"compressed_size\":{},\"symbols\":[{}],\"comparisons\":[{}]}}",
+            self.total, self.entropy, self.average_code_length, self.redundancy,
+            json_efficiency(self.efficiency), self.header_size, self.compressed_size, symbols.join(","), comparisons.join(",")
+        )
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "symbol      count  probability  length  code")?;
+        for s in &self.symbols {
+            writeln!(
+                f, "{:6} {:10} {:12.6} {:7}  {}",
+                format!("{:?}", s.symbol as char), s.count, s.probability, s.code.len, s.code
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "input size:          {} bytes", self.total)?;
+        writeln!(f, "entropy:             {:.6} bits/symbol", self.entropy)?;
+        writeln!(f, "average code length: {:.6} bits/symbol", self.average_code_length)?;
+        writeln!(f, "redundancy:          {:.6} bits/symbol", self.redundancy)?;
+        writeln!(f, "efficiency:          {}", percent(self.efficiency))?;
+        writeln!(f, "header size:         {} bytes", self.header_size)?;
+        writeln!(f, "compressed size:     {} bytes", self.compressed_size)?;
+        if !self.comparisons.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "code                bits/symbol  efficiency")?;
+            writeln!(f, "{:18} {:12.6} {:>11}", "huffman", self.average_code_length, percent(self.efficiency))?;
+            for c in &self.comparisons {
+                writeln!(f, "{:18} {:12.6} {:>11}", c.name, c.average_code_length, percent(c.efficiency))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let stats = Stats::from_data(b"");
+        assert_eq!(stats.total, 0);
+        assert!(stats.symbols.is_empty());
+        assert_eq!(stats.compressed_size, stats.header_size);
+    }
+
+    #[test]
+    fn uniform() {
+        let stats = Stats::from_data(b"abcdabcd");
+        assert_eq!(stats.symbols.len(), 4);
+        assert!((stats.entropy - 2.0).abs() < 1e-9);
+        assert!((stats.average_code_length - 2.0).abs() < 1e-9);
+        assert!(stats.redundancy.abs() < 1e-9);
+        assert_eq!(stats.compressed_size, stats.header_size + 2);
+    }
+
+    #[test]
+    fn skewed() {
+        let stats = Stats::from_data(b"aaaaaaab");
+        assert_eq!(stats.symbols[0].symbol, b'a');
+        assert_eq!(stats.symbols[0].count, 7);
+        assert_eq!(stats.symbols[0].code.len, 1);
+        assert!(stats.entropy < stats.average_code_length);
+        assert!(stats.efficiency.unwrap() < 1.0);
+    }
+
+    #[test]
+    fn comparisons() {
+        let data = b"aaaaaaaaaaaaaaaaaabc".repeat(20);
+        let stats = Stats::from_data(&data);
+        let names: Vec<&str> = stats.comparisons.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["shannon-fano", "tunstall (12 bits)"]);
+        // Huffman is optimal among codes of each byte, Tunstall is not limited to a whole number of bits
+        assert!(stats.comparisons[0].average_code_length >= stats.average_code_length - 1e-9);
+        assert!(stats.comparisons[1].average_code_length < stats.average_code_length);
+        assert!(stats.comparisons.iter().all(|c| c.average_code_length >= stats.entropy));
+        assert!(stats.to_string().contains("tunstall (12 bits)"));
+        assert!(Stats::from_data(b"").comparisons.is_empty());
+    }
+
+    #[test]
+    fn single_symbol() {
+        let stats = Stats::from_data(b"aaaa");
+        assert_eq!(stats.entropy, 0.0);
+        assert!(stats.entropy.is_sign_positive());
+        assert_eq!(stats.efficiency, None);
+        assert!(stats.to_string().contains("entropy:             0.000000 bits/symbol\nav"));
+        assert!(stats.to_string().contains("efficiency:          n/a\n"));
+        assert!(stats.to_json().contains("\"efficiency\":null,"));
+        assert_eq!(Stats::from_data(b"").efficiency, None);
+    }
+
+    #[test]
+    fn single_symbol_comparisons() {
+        let stats = Stats::from_data(b"aaaa");
+        assert!(stats.comparisons.iter().all(|c| c.efficiency.is_none()));
+        // a whole codeword for each byte
+        assert_eq!(stats.comparisons[1].average_code_length, TUNSTALL_BITS as f64);
+        assert!(!stats.to_string().contains('%'));
+        assert!(!stats.to_json().contains("\"efficiency\":1"));
+    }
+
+    #[test]
+    fn json() {
+        let json = Stats::from_data(b"ab").to_json();
+        assert!(json.starts_with("{\"total\":2,"));
+        assert!(json.contains("\"symbol\":97,\"count\":1,\"probability\":0.5,\"code_length\":1"));
+        assert!(json.contains("\"comparisons\":[{\"name\":\"shannon-fano\",\"average_code_length\":1,"));
+        assert!(crate::json::parse(&json).is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+//! Table-based asymmetric numeral systems (tANS), as in the FSE coder of zstd.
+//!
+//! The counts of the bytes are normalized to frequencies adding up to `TABLE_SIZE`,
+//! and each byte is given as many slots of a table of states as its frequency.
+//! Coding a byte moves from a state to one of its slots, writing the low bits of the state:
+//! fewer of them the more frequent the byte, a fractional number of bits on average.
+//!
+//! ```text
+//! header of the frequencies, out of 2^12, as described in `frequencies`
+//! unless the data is empty:
+//!     12 bits: state of the encoder after the last byte, which the decoder starts from
+//!     the bits of each byte, in the order of the data, padded with zeroes to a 8-bit boundary
+//! ```
+//!
+//! Bits are packed least significant first, as in DEFLATE. The encoder codes the data backwards
+//! and the bits of each byte are written in the reverse order, so that decoding goes forward.
+
+use std::io::Write;
+
+use super::bits::{LsbReader, LsbWriter};
+use super::conversion::DECODE_BUFFER_SIZE;
+use super::error::{Error, Result};
+use super::frequencies;
+
+/// Frequencies add up to `1 << TABLE_LOG`.
+pub const TABLE_LOG: u32 = 12;
+
+pub const TABLE_SIZE: usize = 1 << TABLE_LOG;
+
+/// Byte of each slot of the table, the slots of a byte being spread over the table
+/// by a step coprime with its size.
+fn spread(freqs: &[u32; 256]) -> Vec<u8> {
+    const STEP: usize = (TABLE_SIZE >> 1) + (TABLE_SIZE >> 3) + 3;
+    let mut symbols = vec![0; TABLE_SIZE];
+    let mut pos = 0;
+    for (byte, freq) in freqs.iter().enumerate() {
+        for _ in 0..*freq {
+            symbols[pos] = byte as u8;
+            pos = (pos + STEP) % TABLE_SIZE;
+        }
+    }
+    symbols
+}
+
+/// Entry of the decoding table for one state.
+#[derive(Clone, Copy)]
+struct Entry {
+    byte: u8,
+    /// Number of bits read to get the next state.
+    bits: u8,
+    /// Next state, before adding the bits read.
+    base: u16,
+}
+
+fn decoding_table(freqs: &[u32; 256]) -> Vec<Entry> {
+    // the slots of a byte are numbered from its frequency up, in the order of the table
+    let mut next: Vec<usize> = freqs.iter().map(|f| *f as usize).collect();
+    spread(freqs)
+        .into_iter()
+        .map(|byte| {
+            let x = next[byte as usize];
+            next[byte as usize] += 1;
+            let bits = TABLE_LOG - x.ilog2();
+            Entry { byte, bits: bits as u8, base: ((x << bits) - TABLE_SIZE) as u16 }
+        })
+        .collect()
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let freqs = frequencies::normalize(data, TABLE_SIZE as u32);
+    let mut out = frequencies::header(data.len() as u64, &freqs);
+    if data.is_empty() {
+        return out;
+    }
+
+    // state reached from each slot of a byte, the slots of each byte following those of the smaller ones
+    let mut start = [0; 256];
+    for b in 1..256 {
+        start[b] = start[b - 1] + freqs[b - 1] as usize;
+    }
+    let mut states = vec![0; TABLE_SIZE];
+    let mut filled = start;
+    for (slot, byte) in spread(&freqs).into_iter().enumerate() {
+        states[filled[byte as usize]] = (TABLE_SIZE + slot) as u32;
+        filled[byte as usize] += 1;
+    }
+
+    // states are in [TABLE_SIZE, 2 * TABLE_SIZE), each byte takes them to [freq, 2 * freq) by dropping low bits
+    let mut state = TABLE_SIZE as u32;
+    let mut chunks: Vec<(u16, u8)> = Vec::with_capacity(data.len());
+    for byte in data.iter().rev() {
+        let freq = freqs[*byte as usize];
+        let mut bits = state.ilog2() - freq.ilog2();
+        if state >> bits < freq {
+            bits -= 1;
+        }
+        chunks.push(((state & ((1 << bits) - 1)) as u16, bits as u8));
+        state = states[start[*byte as usize] + (state >> bits) as usize - freq as usize];
+    }
+
+    let mut writer = LsbWriter::new();
+    writer.write_bits(state - TABLE_SIZE as u32, TABLE_LOG);
+    for (value, bits) in chunks.iter().rev() {
+        writer.write_bits(*value as u32, *bits as u32);
+    }
+    out.extend(writer.finish());
+    out
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decode_to(data, &mut out)?;
+    Ok(out)
+}
+
+/// Decode `data` into `out` a buffer at a time, as a byte of frequency close to `TABLE_SIZE`
+/// reads no bits most of the time and the size in the header could otherwise decide what is allocated.
+pub fn decode_to<W: Write>(data: &[u8], out: &mut W) -> Result<()> {
+    let (size, freqs, payload) = frequencies::read_header(data, TABLE_SIZE as u32)?;
+    let sum: u32 = freqs.iter().sum();
+    if sum == 0 {
+        if !payload.is_empty() {
+            return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+        }
+        return Ok(());
+    }
+    if sum != TABLE_SIZE as u32 {
+        return Err(Error::InvalidTable(format!("frequencies add up to {} instead of {}", sum, TABLE_SIZE)));
+    }
+
+    let table = decoding_table(&freqs);
+    let mut reader = LsbReader::new(payload);
+    let read_bits = |reader: &mut LsbReader, len: u32| reader.read_bits(len).ok_or(Error::UnexpectedEof);
+    let mut state = read_bits(&mut reader, TABLE_LOG)? as usize;
+    let mut buffer = Vec::with_capacity(DECODE_BUFFER_SIZE);
+    for _ in 0..size {
+        let entry = table[state];
+        buffer.push(entry.byte);
+        state = entry.base as usize + read_bits(&mut reader, entry.bits as u32)? as usize;
+        if buffer.len() == DECODE_BUFFER_SIZE {
+            out.write_all(&buffer)?;
+            buffer.clear();
+        }
+    }
+    // the encoder starts from the first state
+    if state != 0 {
+        return Err(Error::InvalidPayload(format!("final state {} instead of 0", state)));
+    }
+    if reader.position() != payload.len() {
+        return Err(Error::InvalidPayload("trailing bytes after payload".to_string()));
+    }
+    while let Some(bit) = reader.read_bit() {
+        if bit != 0 {
+            return Err(Error::InvalidPayload("padding bits are not zero".to_string()));
+        }
+    }
+    out.write_all(&buffer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{bench, range};
+
+    #[test]
+    fn table() {
+        let freqs = frequencies::normalize(b"abracadabra", TABLE_SIZE as u32);
+        let symbols = spread(&freqs);
+        for (b, freq) in freqs.iter().enumerate() {
+            assert_eq!(symbols.iter().filter(|s| **s as usize == b).count(), *freq as usize);
+        }
+        // every state is reached from exactly one slot of each byte, with the bits read
+        let mut reached = vec![vec![0; TABLE_SIZE]; 256];
+        for entry in decoding_table(&freqs) {
+            let start = entry.base as usize;
+            for count in &mut reached[entry.byte as usize][start..start + (1 << entry.bits)] {
+                *count += 1;
+            }
+        }
+        for (counts, freq) in reached.iter().zip(&freqs) {
+            assert!(counts.iter().all(|c| *c == (*freq > 0) as usize));
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for data in [&b""[..], b"a", b"abracadabra", &[0; 10_000]] {
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+        for (_, corpus) in bench::corpora() {
+            let data = &corpus[..1 << 16];
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn close_to_range_coding() {
+        for (name, corpus) in bench::corpora() {
+            let data = &corpus[..1 << 16];
+            let (tans, range) = (encode(data).len(), range::encode(data).len());
+            assert!(tans < range + range / 100, "{}: {} bytes, {} with range coding", name, tans, range);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let encoded = encode(b"abracadabra");
+        assert!(matches!(decode(&encoded[..encoded.len() - 1]), Err(Error::UnexpectedEof)));
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+        assert!(decode(&[]).is_err());
+        // frequencies adding up to less than 2^12
+        let mut freqs = encoded.clone();
+        freqs[12] -= 1;
+        assert!(matches!(decode(&freqs), Err(Error::InvalidTable(_))));
+        // initial state changed, which ends on another state
+        let mut state = encoded.clone();
+        state[10 + 3 * 5] ^= 1;
+        assert!(decode(&state).is_err());
+    }
+
+    #[test]
+    fn large_size_is_streamed() {
+        // a byte of frequency 2^12 - 1 reads a bit every few thousand bytes
+        let mut data = u64::MAX.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0, 2, b'a', 0x0f, 0xff, b'b', 0, 1]);
+        data.extend_from_slice(&[0; 16]);
+        let mut out = Vec::new();
+        assert!(matches!(decode_to(&data, &mut out), Err(Error::UnexpectedEof)));
+        // what was decoded before running out of bits was written a buffer at a time
+        assert!(!out.is_empty() && out.len().is_multiple_of(DECODE_BUFFER_SIZE));
+    }
+}
@@ -1,15 +1,17 @@
 use std::fmt;
 use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
 
 use super::bits::BitSet;
 
-pub struct Node {
+/// Node of a tree whose leaves are symbols of type `S`, bytes by default.
+pub struct Node<S = u8> {
     occurences: usize,
-    content: Content,
+    content: Content<S>,
 }
 
-impl Node {
-    fn join(left: Node, right: Node) -> Node {
+impl<S> Node<S> {
+    fn join(left: Node<S>, right: Node<S>) -> Node<S> {
         Node {
             occurences: left.occurences + right.occurences,
             content: Content::Parent {
@@ -20,27 +22,38 @@ impl Node {
     }
 }
 
-enum Content {
-    Leaf(u8),
+enum Content<S> {
+    Leaf(S),
     Parent {
-        left:  Box<Node>,
-        right: Box<Node>,
+        left:  Box<Node<S>>,
+        right: Box<Node<S>>,
     },
 }
 
-pub type Tree = Node;
+pub type Tree<S = u8> = Node<S>;
+
+/// Count the occurences of each byte in `data`.
+pub fn count(data: &[u8]) -> HashMap<u8, usize> {
+    let mut counter: HashMap<u8, usize> = HashMap::new();
+    for k in data {
+        let v = match counter.get(k) {
+            Some(count) => count + 1,
+            None        => 1,
+        };
+        counter.insert(*k, v);
+    }
+    counter
+}
 
 impl Tree {
     pub fn from_data(data: &[u8]) -> Tree {
-        let mut counter: HashMap<u8, usize> = HashMap::new();
-        for k in data {
-            let v = match counter.get(&k) {
-                Some(count) => count + 1,
-                None        => 1,
-            };
-            counter.insert(*k, v);
-        }
-        let mut heap: BinaryHeap<Node> = counter
+        Tree::from_counts(&count(data))
+    }
+}
+
+impl<S: Copy + Eq + Hash> Tree<S> {
+    pub fn from_counts(counter: &HashMap<S, usize>) -> Tree<S> {
+        let mut heap: BinaryHeap<Node<S>> = counter
             .iter()
             .map(|(k, v)| Node { occurences: *v, content: Content::Leaf(*k) })
             .collect();
@@ -53,7 +66,11 @@ impl Tree {
         heap.pop().unwrap()
     }
 
-    pub fn to_hash_map(&self) -> HashMap<u8, BitSet> {
+    pub fn occurences(&self) -> usize {
+        self.occurences
+    }
+
+    pub fn to_hash_map(&self) -> HashMap<S, BitSet> {
         match &self.content {
             Content::Leaf(b) => {
                 let mut hm = HashMap::with_capacity(1);
@@ -74,6 +91,39 @@ impl Tree {
             },
         }
     }
+}
+
+impl Tree {
+    /// Graphviz DOT representation of the tree, edges are labeled with
+    /// the bit they add to the code of the leaves below them.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph huffman {\n    node [shape=circle];\n");
+        self.write_dot(&mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    /// Write the node and its children, returns the node's id.
+    fn write_dot(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        match &self.content {
+            Content::Leaf(b) => {
+                out.push_str(&format!(
+                    "    n{} [shape=box, label=\"{}\\n{}\"];\n",
+                    id, dot_escape(*b), self.occurences
+                ));
+            },
+            Content::Parent { left, right } => {
+                out.push_str(&format!("    n{} [label=\"{}\"];\n", id, self.occurences));
+                let left_id = left.write_dot(out, next_id);
+                out.push_str(&format!("    n{} -> n{} [label=\"0\"];\n", id, left_id));
+                let right_id = right.write_dot(out, next_id);
+                out.push_str(&format!("    n{} -> n{} [label=\"1\"];\n", id, right_id));
+            },
+        }
+        id
+    }
 
     fn fmt_spaces(f: &mut fmt::Formatter, level: usize) -> fmt::Result {
         for _ in 0..level {
@@ -85,10 +135,10 @@ impl Tree {
     fn fmt_with_level(&self, f: &mut fmt::Formatter, level: usize) -> fmt::Result {
         match &self.content {
             Content::Leaf(b) => {
-                write!(f, "{} {}\n", self.occurences, b)?;
+                writeln!(f, "{} {}", self.occurences, b)?;
             },
             Content::Parent { left, right } => {
-                write!(f, "NODE {}\n", self.occurences)?;
+                writeln!(f, "NODE {}", self.occurences)?;
                 Tree::fmt_spaces(f, level)?;
                 write!(f, "left: ")?;
                 left.fmt_with_level(f, level + 1)?;
@@ -101,24 +151,34 @@ impl Tree {
     }
 }
 
+/// Byte as it should appear in a DOT label, non-printable bytes are written in hexadecimal.
+fn dot_escape(b: u8) -> String {
+    match b {
+        b'"' | b'\\'  => format!("\\{}", b as char),
+        b' '         => "' '".to_string(),
+        0x21..=0x7e  => (b as char).to_string(),
+        _            => format!("0x{:02x}", b),
+    }
+}
+
 use std::cmp::Ordering;
 
-impl Ord for Node {
+impl<S> Ord for Node<S> {
     fn cmp(&self, other: &Self) -> Ordering {
         // self.occurences.cmp(&other.occurences)
         other.occurences.cmp(&self.occurences) // dirty hack to fake min-heap
     }
 }
 
-impl PartialOrd for Node {
+impl<S> PartialOrd for Node<S> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Eq for Node {}
+impl<S> Eq for Node<S> {}
 
-impl PartialEq for Node {
+impl<S> PartialEq for Node<S> {
     fn eq(&self, other: &Self) -> bool {
         self.occurences == other.occurences
     }
@@ -129,3 +189,31 @@ impl fmt::Debug for Tree {
         self.fmt_with_level(f, 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_dot_leaf() {
+        let dot = Tree::from_data(b"aaa").to_dot();
+        assert_eq!(dot, "digraph huffman {\n    node [shape=circle];\n    n0 [shape=box, label=\"a\\n3\"];\n}\n");
+    }
+
+    #[test]
+    fn to_dot_edges() {
+        let dot = Tree::from_data(b"aab").to_dot();
+        assert!(dot.contains("    n0 [label=\"3\"];\n"));
+        assert!(dot.contains("    n0 -> n1 [label=\"0\"];\n"));
+        assert!(dot.contains("    n0 -> n2 [label=\"1\"];\n"));
+    }
+
+    #[test]
+    fn dot_escape_bytes() {
+        assert_eq!(dot_escape(b'a'), "a");
+        assert_eq!(dot_escape(b'"'), "\\\"");
+        assert_eq!(dot_escape(b'\\'), "\\\\");
+        assert_eq!(dot_escape(b'\n'), "0x0a");
+        assert_eq!(dot_escape(0xff), "0xff");
+    }
+}
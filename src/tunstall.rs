@@ -0,0 +1,234 @@
+//! Tunstall coding: a variable-to-fixed code, where words of the input of varying length
+//! are each coded by a codeword of the same number of bits.
+//!
+//! The dictionary starts with the words of one byte and its most probable word is replaced
+//! by its extensions by each byte while they fit in the codewords. Every input can be split
+//! in words of the dictionary, and frequent bytes make long words, so a byte can take less than a bit.
+//!
+//! This is not a code of each byte as a `Table` is, so it is not used by the container.
+//! The last word of the input may be cut short, the decoder stops after the size of the data.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::bits::{BitReader, BitWriter};
+use super::error::{Error, Result};
+use super::tree;
+
+/// Node of the parse tree waiting to be expanded, by probability.
+struct Leaf {
+    probability: f64,
+    node: usize,
+}
+
+impl PartialEq for Leaf {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Leaf {}
+
+impl PartialOrd for Leaf {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Leaf {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // the first node of the same probability is expanded first
+        self.probability.total_cmp(&other.probability).then(other.node.cmp(&self.node))
+    }
+}
+
+pub struct Tunstall {
+    /// Size of the codewords.
+    pub bits: u32,
+    /// Word of each codeword.
+    pub words: Vec<Vec<u8>>,
+    /// Bytes of the data the dictionary is built from, in increasing order.
+    alphabet: Vec<u8>,
+    /// Child of each node of the parse tree by byte, node 0 being the empty word.
+    children: HashMap<(usize, u8), usize>,
+    /// Codeword of each node, `None` for the words that were expanded.
+    codewords: Vec<Option<usize>>,
+}
+
+impl Tunstall {
+    pub fn from_data(data: &[u8], bits: u32) -> Tunstall {
+        Tunstall::from_counts(&tree::count(data), bits)
+    }
+
+    /// Dictionary of at most `1 << bits` words for bytes of the given counts,
+    /// which must fit in the codewords.
+    ///
+    /// Expanding the word of a single byte would not add any word, so its dictionary is that byte
+    /// alone and each byte of its data takes a whole codeword.
+    pub fn from_counts(counter: &HashMap<u8, usize>, bits: u32) -> Tunstall {
+        let mut alphabet: Vec<u8> = counter.keys().cloned().collect();
+        alphabet.sort_unstable();
+        assert!(bits <= 16 && alphabet.len() <= 1 << bits, "{} bytes do not fit in {} bits", alphabet.len(), bits);
+        let total: usize = counter.values().sum();
+        if alphabet.is_empty() {
+            // only the empty word, which has no codeword
+            return Tunstall { bits, words: Vec::new(), alphabet, children: HashMap::new(), codewords: vec![None] };
+        }
+
+        // parent, byte and probability of each node
+        let mut nodes: Vec<(usize, u8, f64)> = vec![(0, 0, 1.0)];
+        let mut children = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        heap.push(Leaf { probability: 1.0, node: 0 });
+        // the empty word is always expanded, into one leaf per byte
+        let mut leaves = 1;
+        while let Some(leaf) = heap.peek() {
+            if leaf.node != 0 && (alphabet.len() < 2 || leaves + alphabet.len() - 1 > 1 << bits) {
+                break;
+            }
+            let leaf = heap.pop().unwrap();
+            for byte in &alphabet {
+                let probability = leaf.probability * counter[byte] as f64 / total as f64;
+                children.insert((leaf.node, *byte), nodes.len());
+                heap.push(Leaf { probability, node: nodes.len() });
+                nodes.push((leaf.node, *byte, probability));
+            }
+            leaves += alphabet.len() - 1;
+        }
+
+        let mut codewords = vec![None; nodes.len()];
+        let mut words = Vec::new();
+        // codewords in the order the words were made
+        let mut expanded = vec![false; nodes.len()];
+        for (parent, _) in children.keys() {
+            expanded[*parent] = true;
+        }
+        for node in 1..nodes.len() {
+            if expanded[node] {
+                continue;
+            }
+            let mut word = Vec::new();
+            let mut n = node;
+            while n != 0 {
+                word.push(nodes[n].1);
+                n = nodes[n].0;
+            }
+            word.reverse();
+            codewords[node] = Some(words.len());
+            words.push(word);
+        }
+        Tunstall { bits, words, alphabet, children, codewords }
+    }
+
+    /// Code `data` as codewords of `bits` bits, most significant bit first, padded with zeroes.
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut writer = BitWriter::new(Vec::new());
+        let mut node = 0;
+        for byte in data {
+            node = *self.children
+                .get(&(node, *byte))
+                .ok_or_else(|| Error::InvalidPayload(format!("byte {} is not in the dictionary", byte)))?;
+            if let Some(codeword) = self.codewords[node] {
+                writer.write_bits(codeword as u64, self.bits)?;
+                node = 0;
+            }
+        }
+        if node != 0 {
+            // any word starting with the last bytes will do
+            while self.codewords[node].is_none() {
+                node = self.children[&(node, self.alphabet[0])];
+            }
+            writer.write_bits(self.codewords[node].unwrap() as u64, self.bits)?;
+        }
+        Ok(writer.finish()?)
+    }
+
+    /// Decode the first `len` bytes coded in `data`.
+    pub fn decode(&self, data: &[u8], len: usize) -> Result<Vec<u8>> {
+        let mut reader = BitReader::new(data);
+        let mut out = Vec::new();
+        while out.len() < len {
+            let mut codeword = 0;
+            for _ in 0..self.bits {
+                codeword = codeword << 1 | reader.read_bit()?.ok_or(Error::UnexpectedEof)? as usize;
+            }
+            let word = self.words
+                .get(codeword)
+                .ok_or_else(|| Error::InvalidPayload(format!("codeword {} is not in the dictionary", codeword)))?;
+            out.extend_from_slice(word);
+        }
+        out.truncate(len);
+        Ok(out)
+    }
+
+    /// Average number of bytes coded by a codeword, for bytes of the probabilities the dictionary is built from.
+    pub fn average_word_length(&self, counter: &HashMap<u8, usize>) -> f64 {
+        let total: usize = counter.values().sum();
+        self.words
+            .iter()
+            .map(|word| {
+                let probability: f64 = word.iter().map(|b| counter[b] as f64 / total as f64).product();
+                probability * word.len() as f64
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dictionary() {
+        // A with a probability of 0.7, B 0.2 and C 0.1, in 3 bits: A is expanded, then AA
+        let counter: HashMap<u8, usize> = [(b'A', 7), (b'B', 2), (b'C', 1)].iter().cloned().collect();
+        let tunstall = Tunstall::from_counts(&counter, 3);
+        let mut words: Vec<&[u8]> = tunstall.words.iter().map(|w| &w[..]).collect();
+        words.sort_unstable();
+        assert_eq!(words, [&b"AAA"[..], b"AAB", b"AAC", b"AB", b"AC", b"B", b"C"]);
+        // 1 + 0.7 + 0.49 bytes per codeword
+        assert!((tunstall.average_word_length(&counter) - 2.19).abs() < 1e-9);
+    }
+
+    #[test]
+    fn round_trips() {
+        let data = b"abracadabra alakazam".repeat(10);
+        for bits in [5, 8, 12] {
+            let tunstall = Tunstall::from_data(&data, bits);
+            assert!(tunstall.words.len() <= 1 << bits);
+            for len in [0, 1, 2, data.len() - 1, data.len()] {
+                let encoded = tunstall.encode(&data[..len]).unwrap();
+                assert_eq!(tunstall.decode(&encoded, len).unwrap(), &data[..len]);
+            }
+        }
+        let single = Tunstall::from_data(b"aaaa", 4);
+        assert_eq!(single.words, vec![b"a".to_vec()]);
+        assert_eq!(single.decode(&single.encode(b"aaaa").unwrap(), 4).unwrap(), b"aaaa");
+    }
+
+    #[test]
+    fn empty() {
+        let tunstall = Tunstall::from_data(b"", 8);
+        assert!(tunstall.words.is_empty());
+        assert!(tunstall.encode(b"").unwrap().is_empty());
+        assert!(tunstall.decode(&[], 0).unwrap().is_empty());
+        assert!(tunstall.encode(b"a").is_err());
+        assert_eq!(tunstall.average_word_length(&HashMap::new()), 0.0);
+    }
+
+    #[test]
+    fn less_than_a_bit_per_byte() {
+        let data: Vec<u8> = (0..10_000).map(|i| if i % 20 == 0 { b'b' } else { b'a' }).collect();
+        let tunstall = Tunstall::from_data(&data, 8);
+        assert!(tunstall.encode(&data).unwrap().len() * 8 < data.len() / 2);
+    }
+
+    #[test]
+    fn errors() {
+        let tunstall = Tunstall::from_data(b"abc", 4);
+        assert!(tunstall.encode(b"abcd").is_err());
+        assert!(matches!(tunstall.decode(&[], 1), Err(Error::UnexpectedEof)));
+        // codewords past the dictionary
+        assert!(tunstall.decode(&[0xff], 1).is_err());
+    }
+}
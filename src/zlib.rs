@@ -0,0 +1,96 @@
+//! zlib streams (RFC 1950): a DEFLATE stream between a 2 bytes header and the Adler-32 of the data.
+//!
+//! ```text
+//! 1 byte CMF: compression method 8 (DEFLATE) in the low 4 bits, log2 of the window size minus 8 above
+//! 1 byte FLG: bit 5 set if a preset dictionary follows, such that CMF * 256 + FLG is a multiple of 31
+//! DEFLATE stream
+//! 4 byte unsigned int: Adler-32 of the data
+//! ```
+
+use super::checksum::adler32;
+use super::deflate::deflate;
+use super::error::{Error, Result};
+use super::inflate::inflate_prefix;
+
+pub const SUFFIX: &str = ".zz";
+
+/// Header of a stream with a 32 KiB window and the default compression level.
+const HEADER: [u8; 2] = [0x78, 0x9c];
+
+const FLAG_DICT: u8 = 1 << 5;
+
+/// Whether `data` starts with a zlib header.
+pub fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && cmf >> 4 <= 7 && (*cmf as u16 * 256 + *flg as u16).is_multiple_of(31),
+        _              => false,
+    }
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = HEADER.to_vec();
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 2 {
+        return Err(Error::UnexpectedEof);
+    }
+    if !is_zlib(data) {
+        return Err(Error::InvalidHeader("not a zlib stream".to_string()));
+    }
+    if data[1] & FLAG_DICT != 0 {
+        return Err(Error::InvalidHeader("preset dictionaries are not supported".to_string()));
+    }
+    let (out, len) = inflate_prefix(&data[2..])?;
+    let trailer = data.get(2 + len..).ok_or(Error::UnexpectedEof)?;
+    if trailer.len() < 4 {
+        return Err(Error::UnexpectedEof);
+    }
+    if trailer.len() > 4 {
+        return Err(Error::InvalidPayload("trailing bytes after zlib stream".to_string()));
+    }
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let actual = adler32(&out);
+    if expected != actual {
+        return Err(Error::ChecksumMismatch { expected, actual });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn python_stream() {
+        // zlib.compress(b"hello hello hello")
+        let stream = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00, 0x3a, 0x2e, 0x06, 0x7d,
+        ];
+        assert!(is_zlib(&stream));
+        assert_eq!(decompress(&stream).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn round_trip() {
+        for data in [&b""[..], b"a", b"hello hello hello", &[0; 10_000]] {
+            assert_eq!(decompress(&compress(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut stream = compress(b"hello hello hello");
+        assert!(matches!(decompress(&stream[..stream.len() - 1]), Err(Error::UnexpectedEof)));
+        let last = stream.len() - 1;
+        stream[last] ^= 1;
+        assert!(matches!(decompress(&stream), Err(Error::ChecksumMismatch { .. })));
+        assert!(!is_zlib(b"HUFF"));
+        assert!(!is_zlib(&[0x78]));
+        // preset dictionary
+        assert!(decompress(&[0x78, 0xbb, 0, 0, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Memory used by `compress_stream` for each method, measured by the allocator of this test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use huffman::bench::Lcg;
+use huffman::container::{self, Method, Options};
+
+/// System allocator keeping track of the bytes allocated and of their peak.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// `len` bytes of a generator restarted on every seek, so that none of them is stored.
+struct Generated {
+    len: u64,
+    position: u64,
+    rng: Lcg,
+}
+
+impl Generated {
+    fn new(len: u64) -> Generated {
+        Generated { len, position: 0, rng: Lcg(1) }
+    }
+}
+
+impl Read for Generated {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.len.saturating_sub(self.position) as usize);
+        for b in &mut buf[..n] {
+            // skewed towards small bytes, so that the Huffman coding is smaller than storing
+            *b = (self.rng.next_u32() | 0x100).trailing_zeros() as u8;
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Generated {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Start(0) => *self = Generated::new(self.len),
+            SeekFrom::Current(0) => {},
+            _ => return Err(io::Error::other("only rewinding is supported")),
+        }
+        Ok(self.position)
+    }
+}
+
+#[test]
+fn streamed_methods_are_bounded() {
+    // 8 chunks of 64 KiB, of which the streamed methods hold 2 at a time
+    let len = 1 << 19;
+    for method in Method::ALL.iter().cloned().filter(|m| m.is_streamed()) {
+        let before = ALLOCATED.load(Ordering::SeqCst);
+        PEAK.store(before, Ordering::SeqCst);
+        let options = Options { method: Some(method), ..Options::default() };
+        container::compress_stream(Generated::new(len), io::sink(), &options).unwrap();
+        let used = PEAK.load(Ordering::SeqCst) - before;
+        assert!(used < len as usize / 2, "{} used {} bytes", method.name(), used);
+    }
+}